@@ -0,0 +1,680 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::config::AppConfig;
+use crate::http;
+use crate::provider::{Host, HostKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CredentialSource {
+    Env(&'static str),
+    // Saved by `ghgrab auth login`
+    Stored,
+    GhCli,
+    Netrc,
+    // Installation token minted for a GitHub App
+    App(u64),
+}
+
+impl fmt::Display for CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialSource::Env(var) => write!(f, "{}", var),
+            CredentialSource::Stored => write!(f, "ghgrab login"),
+            CredentialSource::GhCli => write!(f, "gh hosts.yml"),
+            CredentialSource::Netrc => write!(f, ".netrc"),
+            CredentialSource::App(id) => write!(f, "GitHub App {}", id),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Credential {
+    pub token: String,
+    pub source: CredentialSource,
+}
+
+// Keep tokens out of logs and panic messages
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credential")
+            .field("token", &"<redacted>")
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl Credential {
+    // `host` is the instance name ("github.com", a GHES host, ...), `api_host` where its API lives.
+    // GitHub order: environment, our own login, then gh's hosts.yml, then ~/.netrc.
+    // GitLab has no login of ours or gh's, so only the environment and ~/.netrc apply.
+    // Bitbucket app passwords go with a username; `token` then holds `user:password`.
+    pub fn discover(kind: HostKind, host: &str, api_host: &str) -> Option<Credential> {
+        let found = Self::from_env(env_vars(kind, host));
+        let found = match kind {
+            HostKind::GitHub => found
+                .or_else(|| Self::from_store(host))
+                .or_else(|| Self::from_gh_hosts(host)),
+            HostKind::GitLab | HostKind::Gitea => found,
+            HostKind::Bitbucket => Self::from_app_password(),
+        };
+        found.or_else(|| Self::from_netrc(&[host, api_host], kind == HostKind::Bitbucket))
+    }
+
+    fn from_env(vars: &[&'static str]) -> Option<Credential> {
+        vars.iter().find_map(|var| {
+            let token = env::var(var).ok().filter(|t| !t.trim().is_empty())?;
+            Some(Credential {
+                token: token.trim().to_string(),
+                source: CredentialSource::Env(var),
+            })
+        })
+    }
+
+    fn from_app_password() -> Option<Credential> {
+        let user = env::var("BITBUCKET_USERNAME").ok().filter(|u| !u.trim().is_empty())?;
+        let password = Self::from_env(&["BITBUCKET_APP_PASSWORD"])?;
+        Some(Credential {
+            token: format!("{}:{}", user.trim(), password.token),
+            ..password
+        })
+    }
+
+    fn from_store(host: &str) -> Option<Credential> {
+        let token = CredentialStore::load().ok()?.hosts.remove(host)?.token;
+        Some(Credential {
+            token,
+            source: CredentialSource::Stored,
+        })
+    }
+
+    fn from_gh_hosts(host: &str) -> Option<Credential> {
+        let dir = match env::var("GH_CONFIG_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => dirs::home_dir()?.join(".config").join("gh"),
+        };
+        let text = fs::read_to_string(dir.join("hosts.yml")).ok()?;
+        let token = gh_hosts_token(&text, host)?;
+        Some(Credential {
+            token,
+            source: CredentialSource::GhCli,
+        })
+    }
+
+    // With `with_login`, only entries naming a login count, and the token is `login:password`
+    fn from_netrc(machines: &[&str], with_login: bool) -> Option<Credential> {
+        let path = match env::var("NETRC") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => dirs::home_dir()?.join(if cfg!(windows) { "_netrc" } else { ".netrc" }),
+        };
+        let text = fs::read_to_string(path).ok()?;
+        let token = machines.iter().find_map(|m| match netrc_entry(&text, m)? {
+            (Some(login), password) if with_login => Some(format!("{}:{}", login, password)),
+            (_, password) if !with_login => Some(password),
+            _ => None,
+        })?;
+        Some(Credential {
+            token,
+            source: CredentialSource::Netrc,
+        })
+    }
+}
+
+// Discovered credential per instance name, looked up once
+#[derive(Default)]
+pub struct CredentialCache(Mutex<HashMap<String, Option<Credential>>>);
+
+impl CredentialCache {
+    pub fn get(&self, host: &Host) -> Option<Credential> {
+        let mut cache = self.0.lock().unwrap();
+        cache
+            .entry(host.name().to_string())
+            .or_insert_with(|| {
+                let api_host = Url::parse(&host.api)
+                    .ok()
+                    .and_then(|u| u.host_str().map(str::to_string))
+                    .unwrap_or_default();
+                Credential::discover(host.kind, host.name(), &api_host)
+            })
+            .clone()
+    }
+}
+
+// Put a credential in the `name` header, kept out of debug output
+pub fn authorize(request: &mut reqwest::Request, name: HeaderName, value: &str) -> Result<()> {
+    let mut value = HeaderValue::from_str(value).context("Invalid characters in token")?;
+    value.set_sensitive(true);
+    request.headers_mut().insert(name, value);
+    Ok(())
+}
+
+// A 401 to a request that carried `credential` means the token itself was turned down;
+// without one it is left to the client's own status check
+pub fn check_rejected(response: reqwest::Response, credential: Option<&Credential>) -> Result<reqwest::Response> {
+    if response.status().as_u16() == 401 {
+        if let Some(credential) = credential {
+            return Err(anyhow!(
+                "Authentication failed: the token from {} was rejected (expired or revoked?)",
+                credential.source
+            ));
+        }
+    }
+    Ok(response)
+}
+
+// Token variables for an instance; each only ever applies to the hosts it was meant for
+pub fn env_vars(kind: HostKind, host: &str) -> &'static [&'static str] {
+    match kind {
+        HostKind::GitHub if host == "github.com" => &["GITHUB_TOKEN", "GH_TOKEN"],
+        HostKind::GitHub => &["GH_ENTERPRISE_TOKEN", "GITHUB_ENTERPRISE_TOKEN"],
+        // glab's convention: the token is for GITLAB_HOST, or for gitlab.com when that is unset
+        HostKind::GitLab => {
+            let token_host = env::var("GITLAB_HOST").unwrap_or_else(|_| "gitlab.com".to_string());
+            let token_host = token_host.split_once("://").map_or(token_host.as_str(), |(_, rest)| rest);
+            if token_host.trim_end_matches('/') == host {
+                &["GITLAB_TOKEN", "GL_TOKEN"]
+            } else {
+                &[]
+            }
+        }
+        // app passwords need a username too; see Credential::from_app_password
+        HostKind::Bitbucket => &[],
+        // tea's convention, scoped to GITEA_SERVER_URL the same way
+        HostKind::Gitea => {
+            let server = env::var("GITEA_SERVER_URL").unwrap_or_default();
+            let server = server.split_once("://").map_or(server.as_str(), |(_, rest)| rest);
+            if server.trim_end_matches('/') == host {
+                &["GITEA_SERVER_TOKEN"]
+            } else if host == "codeberg.org" {
+                &["CODEBERG_TOKEN"]
+            } else {
+                &[]
+            }
+        }
+    }
+}
+
+// Authenticates as a GitHub App installation: a short-lived RS256 JWT signed with the app's
+// key is exchanged for an installation token, which is reused until shortly before it expires
+pub struct AppAuth {
+    pub app_id: u64,
+    pub installation_id: u64,
+    // Instance name the app is installed on
+    pub host: String,
+    key: jsonwebtoken::EncodingKey,
+    token: tokio::sync::Mutex<Option<InstallationToken>>,
+}
+
+#[derive(Clone, Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: String,
+}
+
+#[derive(Serialize)]
+struct AppClaims {
+    iat: u64,
+    exp: u64,
+    iss: String,
+}
+
+// Refresh this long before GitHub's expiry so in-flight requests never carry a stale token
+const REFRESH_MARGIN: u64 = 5 * 60;
+
+impl AppAuth {
+    // GHGRAB_APP_ID, GHGRAB_APP_INSTALLATION_ID and GHGRAB_APP_PRIVATE_KEY (PEM text or a path)
+    // override the `[app]` table of the config
+    pub fn load(config: &AppConfig) -> Result<Option<Self>> {
+        fn var_id(name: &str) -> Result<Option<u64>> {
+            match env::var(name) {
+                Ok(value) => value
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| anyhow!("{} must be a number", name)),
+                Err(_) => Ok(None),
+            }
+        }
+
+        let app_id = var_id("GHGRAB_APP_ID")?.or(config.id);
+        let installation_id = var_id("GHGRAB_APP_INSTALLATION_ID")?.or(config.installation_id);
+        let key = env::var("GHGRAB_APP_PRIVATE_KEY")
+            .ok()
+            .or_else(|| config.private_key.clone());
+
+        let (app_id, installation_id, key) = match (app_id, installation_id, key) {
+            (None, None, None) => return Ok(None),
+            (Some(app), Some(installation), Some(key)) => (app, installation, key),
+            _ => {
+                return Err(anyhow!(
+                    "GitHub App auth needs an app ID, an installation ID and a private key"
+                ));
+            }
+        };
+
+        let pem = if key.contains("-----BEGIN") {
+            key
+        } else {
+            fs::read_to_string(&key).context(format!("Failed to read private key: {}", key))?
+        };
+        let key = jsonwebtoken::EncodingKey::from_rsa_pem(pem.as_bytes())
+            .context("Invalid GitHub App private key (expected an RSA PEM)")?;
+
+        Ok(Some(AppAuth {
+            app_id,
+            installation_id,
+            host: config.host.clone().unwrap_or_else(|| "github.com".to_string()),
+            key,
+            token: tokio::sync::Mutex::new(None),
+        }))
+    }
+
+    fn jwt(&self, now: u64) -> Result<String> {
+        // backdated a minute for clock drift; GitHub rejects lifetimes over ten minutes
+        let claims = AppClaims {
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: self.app_id.to_string(),
+        };
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+            &claims,
+            &self.key,
+        )
+        .context("Failed to sign GitHub App JWT")
+    }
+
+    // Current installation token, minting a new one through `api` when it is missing or about to expire
+    pub async fn token(&self, client: &reqwest::Client, api: &str) -> Result<Credential> {
+        let now = unix_now();
+        let mut cached = self.token.lock().await;
+        let fresh = cached
+            .as_ref()
+            .and_then(|t| parse_timestamp(&t.expires_at))
+            .is_some_and(|expires| expires > now + REFRESH_MARGIN);
+
+        if !fresh {
+            let response = client
+                .post(format!("{}/app/installations/{}/access_tokens", api, self.installation_id))
+                .header("Accept", "application/vnd.github+json")
+                .bearer_auth(self.jwt(now)?)
+                .send()
+                .await
+                .context("Failed to request a GitHub App installation token")?;
+            if !response.status().is_success() {
+                return Err(anyhow!(
+                    "GitHub App {} could not get a token for installation {}: {}",
+                    self.app_id,
+                    self.installation_id,
+                    response.status()
+                ));
+            }
+            *cached = Some(
+                response
+                    .json()
+                    .await
+                    .context("Failed to parse installation token response")?,
+            );
+        }
+
+        Ok(Credential {
+            token: cached.as_ref().map(|t| t.token.clone()).unwrap_or_default(),
+            source: CredentialSource::App(self.app_id),
+        })
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// `2024-01-31T12:00:00Z` to Unix seconds; GitHub always reports UTC
+fn parse_timestamp(text: &str) -> Option<u64> {
+    let (date, time) = text.trim_end_matches('Z').split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(|p| p.get(..2)?.parse::<i64>().ok());
+    let (hh, mm, ss) = (time.next()??, time.next()??, time.next()??);
+
+    // days since the epoch for a proleptic Gregorian date
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    u64::try_from(days * 86400 + hh * 3600 + mm * 60 + ss).ok()
+}
+
+// Tokens from `ghgrab auth login`, one table per host in credentials.toml
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CredentialStore {
+    #[serde(default)]
+    pub hosts: BTreeMap<String, StoredToken>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredToken {
+    pub token: String,
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl fmt::Debug for StoredToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoredToken")
+            .field("token", &"<redacted>")
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+impl CredentialStore {
+    // GHGRAB_CREDENTIALS wins over the per-user config directory
+    pub fn path() -> Option<PathBuf> {
+        if let Ok(path) = env::var("GHGRAB_CREDENTIALS") {
+            return Some(PathBuf::from(path));
+        }
+        dirs::config_dir().map(|dir| dir.join("ghgrab").join("credentials.toml"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path().ok_or_else(|| anyhow!("No config directory available"))?;
+        if !path.exists() {
+            return Ok(CredentialStore::default());
+        }
+        let text = fs::read_to_string(&path)
+            .context(format!("Failed to read credentials: {:?}", path))?;
+        toml::from_str(&text).context(format!("Invalid credentials file: {:?}", path))
+    }
+
+    // The file holds secrets, so it is created owner-only and tightened if it already existed
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = Self::path().ok_or_else(|| anyhow!("No config directory available"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(format!("Failed to create directory: {:?}", parent))?;
+        }
+        let text = toml::to_string(self).context("Failed to serialize credentials")?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                    .context(format!("Failed to set permissions on {:?}", path))?;
+            }
+        }
+        let mut file = options
+            .open(&path)
+            .context(format!("Failed to write credentials: {:?}", path))?;
+        file.write_all(text.as_bytes())
+            .context(format!("Failed to write credentials: {:?}", path))?;
+        Ok(path)
+    }
+}
+
+// OAuth device authorization grant (RFC 8628) against `<endpoint>/login/device/code`
+// and `<endpoint>/login/oauth/access_token`
+pub struct DeviceFlow {
+    client: reqwest::Client,
+    endpoint: String,
+    client_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    #[serde(default)]
+    scope: String,
+    error: Option<String>,
+    error_description: Option<String>,
+    interval: Option<u64>,
+}
+
+impl DeviceFlow {
+    pub fn new(endpoint: &str, client_id: &str) -> Result<Self> {
+        Ok(DeviceFlow {
+            client: http::client()?,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+        })
+    }
+
+    pub async fn request_code(&self, scope: &str) -> Result<DeviceCode> {
+        let response = self
+            .client
+            .post(format!("{}/login/device/code", self.endpoint))
+            .header("Accept", "application/json")
+            .form(&[("client_id", self.client_id.as_str()), ("scope", scope)])
+            .send()
+            .await
+            .context("Failed to reach the device authorization endpoint")?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Device authorization failed: {}", response.status()));
+        }
+        response
+            .json()
+            .await
+            .context("Failed to parse device authorization response")
+    }
+
+    // Poll until the user approves the code in the browser, it is denied, or it expires
+    pub async fn poll_token(&self, code: &DeviceCode) -> Result<StoredToken> {
+        let mut interval = code.interval;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(code.expires_in);
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if tokio::time::Instant::now() > deadline {
+                return Err(anyhow!("The device code expired before it was approved"));
+            }
+
+            let response: TokenResponse = self
+                .client
+                .post(format!("{}/login/oauth/access_token", self.endpoint))
+                .header("Accept", "application/json")
+                .form(&[
+                    ("client_id", self.client_id.as_str()),
+                    ("device_code", code.device_code.as_str()),
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ])
+                .send()
+                .await
+                .context("Failed to reach the token endpoint")?
+                .json()
+                .await
+                .context("Failed to parse token response")?;
+
+            if let Some(token) = response.access_token {
+                return Ok(StoredToken {
+                    token,
+                    scope: response.scope,
+                });
+            }
+            match response.error.as_deref() {
+                Some("authorization_pending") => {}
+                Some("slow_down") => interval = response.interval.unwrap_or(interval + 5),
+                Some("expired_token") => return Err(anyhow!("The device code expired before it was approved")),
+                Some("access_denied") => return Err(anyhow!("Login was cancelled in the browser")),
+                Some(other) => {
+                    return Err(anyhow!(
+                        "Login failed: {}",
+                        response.error_description.as_deref().unwrap_or(other)
+                    ));
+                }
+                None => return Err(anyhow!("Token endpoint returned neither a token nor an error")),
+            }
+        }
+    }
+}
+
+// Just enough YAML for gh's hosts.yml: the first `oauth_token` inside the host's block
+fn gh_hosts_token(text: &str, host: &str) -> Option<String> {
+    let mut in_host = false;
+    for line in text.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            in_host = line.trim_end().trim_end_matches(':').trim_matches('"') == host;
+            continue;
+        }
+        if in_host
+            && let Some(token) = line.trim().strip_prefix("oauth_token:")
+        {
+            let token = token.trim().trim_matches('"').trim_matches('\'');
+            if !token.is_empty() {
+                return Some(token.to_string());
+            }
+        }
+    }
+    None
+}
+
+// netrc is a flat token stream: `machine <name> login <user> password <secret>`
+fn netrc_entry(text: &str, machine: &str) -> Option<(Option<String>, String)> {
+    let mut tokens = text.split_whitespace();
+    let mut in_machine = false;
+    let mut login = None;
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                in_machine = tokens.next() == Some(machine);
+                login = None;
+            }
+            "default" => in_machine = false,
+            "login" => login = tokens.next().map(str::to_string),
+            "password" => {
+                let password = tokens.next();
+                if in_machine {
+                    return password.map(|p| (login, p.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gh_hosts_token() {
+        let text = "github.com:\n    user: octocat\n    oauth_token: gho_abc\n    git_protocol: https\nghe.example.com:\n    oauth_token: \"ghe_def\"\n";
+        assert_eq!(gh_hosts_token(text, "github.com").as_deref(), Some("gho_abc"));
+        assert_eq!(gh_hosts_token(text, "ghe.example.com").as_deref(), Some("ghe_def"));
+        assert_eq!(gh_hosts_token(text, "gitlab.com"), None);
+    }
+
+    #[test]
+    fn test_netrc_entry() {
+        let text = "machine example.com login a password b\nmachine api.github.com\n  login octocat\n  password ghp_xyz\nmachine bitbucket.org password c\n";
+        assert_eq!(
+            netrc_entry(text, "api.github.com"),
+            Some((Some("octocat".to_string()), "ghp_xyz".to_string()))
+        );
+        assert_eq!(netrc_entry(text, "bitbucket.org"), Some((None, "c".to_string())));
+        assert_eq!(netrc_entry(text, "github.com"), None);
+    }
+
+    // Minimal stand-in for the device endpoints: one pending poll, then a token
+    async fn serve_device_flow(listener: tokio::net::TcpListener) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut polls = 0;
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // read the headers plus the form body announced by Content-Length
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let text = String::from_utf8_lossy(&request);
+            let body = if text.starts_with("POST /login/device/code") {
+                r#"{"device_code":"dc","user_code":"ABCD-1234","verification_uri":"http://localhost/device","expires_in":60,"interval":0}"#
+            } else {
+                polls += 1;
+                if polls == 1 {
+                    r#"{"error":"authorization_pending"}"#
+                } else {
+                    r#"{"access_token":"gho_test","scope":"repo","token_type":"bearer"}"#
+                }
+            };
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_device_flow_against_stand_in() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_device_flow(listener));
+
+        let flow = DeviceFlow::new(&endpoint, "client").unwrap();
+        let code = flow.request_code("repo").await.unwrap();
+        assert_eq!(code.user_code, "ABCD-1234");
+        let token = flow.poll_token(&code).await.unwrap();
+        assert_eq!(token.token, "gho_test");
+        assert_eq!(token.scope, "repo");
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2024-02-29T12:34:56Z"), Some(1709210096));
+        assert_eq!(parse_timestamp("not a date"), None);
+    }
+
+    #[test]
+    fn test_debug_redacts_token() {
+        let cred = Credential {
+            token: "ghp_secret".to_string(),
+            source: CredentialSource::Netrc,
+        };
+        assert!(!format!("{:?}", cred).contains("ghp_secret"));
+    }
+}
@@ -0,0 +1,373 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::AUTHORIZATION;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::auth::CredentialSource;
+use crate::provider::{
+    found, resolve_lfs_pointers, resolve_repo_ref, Backend, Host, HostKind, Listing, Progress, Provider, RefCache, RefKind, RefLookup, RefNames,
+    RepoItem, RepoUrl, Target,
+};
+use crate::rest::{encode, encode_path, RestClient};
+
+// The API's largest page size
+const PAGE_LEN: usize = 100;
+// A folder listing stops after this many pages and is marked partial
+const MAX_PAGES: usize = 100;
+// Folder levels one recursive `/src` call goes down; deeper folders are listed again
+const MAX_DEPTH: usize = 10;
+
+pub const BACKEND: Backend = Backend {
+    kind: HostKind::Bitbucket,
+    new_client: || Ok(Box::new(BitbucketClient::new()?)),
+    parse_web,
+    parse_public: |url, path_segments| {
+        (url.host_str() == Some("bitbucket.org")).then(|| parse_web(Host::bitbucket(), path_segments))
+    },
+    blob: "src",
+    line_anchor: |range| format!("lines-{}:{}", range.start, range.end),
+    // `src` links only turn out to be files once resolved
+    anchor_on_dirs: true,
+};
+
+// bitbucket.org/<workspace>/<repo>[/src|raw/<ref>/<path>]
+pub fn parse_web(host: Host, path_segments: &[&str]) -> Result<RepoUrl> {
+    let [workspace, repo, rest @ ..] = path_segments else {
+        return Err(anyhow!("URL must contain workspace and repository"));
+    };
+
+    let (reference, path, target) = match rest {
+        [] | ["src"] => (None, String::new(), Target::Dir),
+        // `src` shows files and folders alike; resolve_ref tells them apart
+        [view @ ("src" | "raw"), reference, path @ ..] => {
+            let path = path.join("/");
+            let target = if *view == "raw" && !path.is_empty() {
+                Target::File
+            } else {
+                Target::Dir
+            };
+            (Some(reference.to_string()), path, target)
+        }
+        [view, ..] => return Err(anyhow!("Bitbucket /{} links are not supported", view)),
+    };
+
+    Ok(RepoUrl {
+        host,
+        owner: workspace.to_string(),
+        repo: repo.strip_suffix(".git").unwrap_or(repo).to_string(),
+        // `feature/x/src` could be branch `feature` or branch `feature/x`
+        ambiguous_ref: !path.is_empty(),
+        reference,
+        pinned: None,
+        path,
+        target,
+        lines: None,
+    })
+}
+
+fn repo_api_url(url: &RepoUrl) -> String {
+    format!("{}/repositories/{}/{}", url.host.api, encode(&url.owner), encode(&url.repo))
+}
+
+// A file's raw content, or with `?format=meta` its metadata
+fn src_url(repo: &str, commit: &str, path: &str) -> String {
+    format!("{}/src/{}/{}", repo, commit, encode_path(path))
+}
+
+// Folders are listed with a trailing slash
+fn src_dir_url(repo: &str, commit: &str, path: &str) -> String {
+    if path.is_empty() {
+        format!("{}/src/{}/", repo, commit)
+    } else {
+        format!("{}/", src_url(repo, commit, path))
+    }
+}
+
+// `<repo>/src/<commit>/<path>/` back into the repository's API URL and the commit
+fn split_src_url(url: &str) -> Option<(String, String)> {
+    // a workspace or repository may well be called `src` itself
+    let start = url.find("/repositories/")? + "/repositories/".len();
+    let mut segments = url[start..].splitn(5, '/');
+    let (workspace, repo) = (segments.next()?, segments.next()?);
+    match (segments.next()?, segments.next()?) {
+        ("src", commit) if !commit.is_empty() => {
+            Some((format!("{}{}/{}", &url[..start], workspace, repo), commit.to_string()))
+        }
+        _ => None,
+    }
+}
+
+// One page of a cursor-paginated list; `next` is the full URL of the following page
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    values: Vec<T>,
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoInfo {
+    // None for a repository without commits
+    mainbranch: Option<RefName>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefName {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefInfo {
+    target: CommitInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitInfo {
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SrcEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    path: String,
+    size: Option<u64>,
+}
+
+impl SrcEntry {
+    // Paths are from the repository root; anything but files and folders is left out
+    fn into_item(self, repo: &str, commit: &str) -> Option<RepoItem> {
+        let name = self.path.rsplit('/').next().unwrap_or_default().to_string();
+        let (item_type, url, download_url) = match self.kind.as_str() {
+            "commit_directory" => ("dir", src_dir_url(repo, commit, &self.path), None),
+            "commit_file" => {
+                let url = src_url(repo, commit, &self.path);
+                ("file", url.clone(), Some(url))
+            }
+            _ => return None,
+        };
+        Some(RepoItem {
+            name,
+            item_type: item_type.to_string(),
+            path: self.path,
+            download_url,
+            url,
+            size: self.size,
+            ..Default::default()
+        })
+    }
+}
+
+pub struct BitbucketClient {
+    rest: RestClient,
+    ref_cache: RefCache,
+}
+
+impl BitbucketClient {
+    pub fn new() -> Result<Self> {
+        Ok(BitbucketClient {
+            // app passwords are sent as HTTP Basic `username:password`
+            rest: RestClient::new(Host::bitbucket(), |token| {
+                (AUTHORIZATION, format!("Basic {}", STANDARD.encode(token)))
+            })?,
+            ref_cache: RefCache::default(),
+        })
+    }
+
+    // Follow `next` cursors through a list; true if `max_pages` cut it short
+    async fn get_pages<T: DeserializeOwned>(&self, url: &str, max_pages: usize) -> Result<(Vec<T>, bool)> {
+        let mut items = Vec::new();
+        let mut next = Some(url.to_string());
+        for _ in 0..max_pages {
+            let Some(url) = next else {
+                return Ok((items, false));
+            };
+            let page: Page<T> = self.rest.get_json(&url).await?;
+            items.extend(page.values);
+            next = page.next;
+        }
+        Ok((items, next.is_some()))
+    }
+
+    // Every file below `dir`, listing `max_depth` levels per call; None if a listing was cut short
+    async fn walk_src(&self, dir: &RepoItem, max_depth: usize) -> Result<Option<Vec<RepoItem>>> {
+        let mut files = Vec::new();
+        let mut pending = vec![(dir.url.clone(), dir.path.clone())];
+        while let Some((dir_url, dir_path)) = pending.pop() {
+            let (items, truncated) = self.list_src(&dir_url, max_depth).await?;
+            if truncated {
+                return Ok(None);
+            }
+            for item in items {
+                if !item.is_dir() {
+                    files.push(item);
+                    continue;
+                }
+                // entry paths are from the repository root
+                let relative = item.path.strip_prefix(dir_path.as_str()).unwrap_or(&item.path).trim_start_matches('/');
+                if relative.split('/').count() >= max_depth {
+                    pending.push((item.url, item.path));
+                }
+            }
+        }
+        Ok(Some(files))
+    }
+
+    // Files and folders at a folder's listing URL, `max_depth` levels down; true if the
+    // listing was cut short
+    async fn list_src(&self, dir_url: &str, max_depth: usize) -> Result<(Vec<RepoItem>, bool)> {
+        let (repo, commit) = split_src_url(dir_url).ok_or_else(|| anyhow!("Not a repository folder: {}", dir_url))?;
+        let listing_url = format!("{}?pagelen={}&max_depth={}", dir_url, PAGE_LEN, max_depth);
+        let (entries, truncated) = self.get_pages::<SrcEntry>(&listing_url, MAX_PAGES).await?;
+        let items = entries
+            .into_iter()
+            .filter_map(|entry| entry.into_item(&repo, &commit))
+            .collect();
+        Ok((items, truncated))
+    }
+}
+
+#[async_trait]
+impl RefLookup for BitbucketClient {
+    fn ref_cache(&self) -> &RefCache {
+        &self.ref_cache
+    }
+
+    async fn default_branch(&self, url: &RepoUrl) -> Result<String> {
+        let info: RepoInfo = self.rest.get_json(&repo_api_url(url)).await?;
+        let branch = info.mainbranch.ok_or_else(|| anyhow!("Repository is empty"))?;
+        Ok(branch.name)
+    }
+
+    async fn fetch_ref_names(&self, url: &RepoUrl) -> Result<RefNames> {
+        let repo = repo_api_url(url);
+        let mut names = RefNames::default();
+        for kind in ["branches", "tags"] {
+            let refs_url = format!("{}/refs/{}?pagelen={}", repo, kind, PAGE_LEN);
+            let (refs, _) = self.get_pages::<RefName>(&refs_url, MAX_PAGES).await?;
+            let found = refs.into_iter().map(|r| r.name);
+            match kind {
+                "branches" => names.branches.extend(found),
+                _ => names.tags.extend(found),
+            }
+        }
+        Ok(names)
+    }
+
+    // `commit/<rev>` only takes SHAs reliably, so branches and tags go through their refs
+    async fn ref_sha(&self, url: &RepoUrl, kind: RefKind, name: &str) -> Result<Option<String>> {
+        let repo = repo_api_url(url);
+        let sha = match kind {
+            RefKind::Branch | RefKind::Tag => {
+                let list = if kind == RefKind::Branch { "branches" } else { "tags" };
+                let lookup = format!("{}/refs/{}/{}", repo, list, encode(name));
+                found(self.rest.get_json::<RefInfo>(&lookup).await)?.map(|info| info.target.hash)
+            }
+            RefKind::Commit => {
+                let lookup = format!("{}/commit/{}", repo, encode(name));
+                found(self.rest.get_json::<CommitInfo>(&lookup).await)?.map(|commit| commit.hash)
+            }
+        };
+        Ok(sha)
+    }
+
+    // the `meta` format of a `src` path tells a file (`commit_file`) from a folder
+    async fn is_file(&self, url: &RepoUrl) -> Result<bool> {
+        let commit = url.commitish().unwrap_or("HEAD");
+        let meta_url = format!("{}?format=meta", src_url(&repo_api_url(url), commit, &url.path));
+        let meta: SrcEntry = self.rest.get_json(&meta_url).await?;
+        Ok(meta.kind == "commit_file")
+    }
+}
+
+#[async_trait]
+impl Provider for BitbucketClient {
+    fn set_progress(&self, progress: Progress) {
+        self.rest.set_progress(progress);
+    }
+
+    fn credential_source(&self, host: &Host) -> Option<CredentialSource> {
+        self.rest.credential_source(host)
+    }
+
+    async fn resolve_ref(&self, url: &mut RepoUrl) -> Result<()> {
+        resolve_repo_ref(self, url).await
+    }
+
+    async fn list_dir(&self, url: &RepoUrl) -> Result<Listing> {
+        let commit = url.commitish().unwrap_or("HEAD");
+        let (items, partial) = self
+            .list_src(&src_dir_url(&repo_api_url(url), commit, &url.path), 1)
+            .await?;
+        Ok(Listing { items, partial })
+    }
+
+    // Recursive `/src` listings cover `MAX_DEPTH` levels per call. Trees too large for one
+    // listing are walked one level per call instead.
+    async fn walk_tree(&self, dir: &RepoItem) -> Result<Vec<RepoItem>> {
+        if let Some(files) = self.walk_src(dir, MAX_DEPTH).await? {
+            return Ok(files);
+        }
+        self.rest
+            .report(format!("{} is too large to list in one go, walking it folder by folder", dir.name));
+        self.walk_src(dir, 1)
+            .await?
+            .ok_or_else(|| anyhow!("A folder below {} has more than {} entries", dir.name, MAX_PAGES * PAGE_LEN))
+    }
+
+    async fn raw_file(&self, url: &str) -> Result<Vec<u8>> {
+        self.rest.download(url).await
+    }
+
+    async fn resolve_lfs(&self, items: &mut [RepoItem], url: &RepoUrl) {
+        resolve_lfs_pointers(&self.rest, items, url, |_| None).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::HostKind;
+
+    #[test]
+    fn test_parse_bitbucket_urls() {
+        let parsed = RepoUrl::parse("https://bitbucket.org/vendor/sdk/src/release/2.x/include/sdk.h#lines-5:9").unwrap();
+        assert_eq!(parsed.host.kind, HostKind::Bitbucket);
+        assert_eq!(parsed.host.api, "https://api.bitbucket.org/2.0");
+        assert_eq!((parsed.owner.as_str(), parsed.repo.as_str()), ("vendor", "sdk"));
+        assert_eq!(parsed.reference.as_deref(), Some("release"));
+        assert_eq!(parsed.path, "2.x/include/sdk.h");
+        assert!(parsed.ambiguous_ref);
+        assert_eq!(parsed.lines.map(|l| (l.start, l.end)), Some((5, 9)));
+
+        let parsed = RepoUrl::parse("bitbucket.org/vendor/sdk").unwrap();
+        assert_eq!(repo_api_url(&parsed), "https://api.bitbucket.org/2.0/repositories/vendor/sdk");
+        assert_eq!(parsed.reference, None);
+
+        let parsed = RepoUrl::parse("git@bitbucket.org:vendor/sdk.git").unwrap();
+        assert_eq!(parsed.repo, "sdk");
+
+        assert!(RepoUrl::parse("https://bitbucket.org/vendor").is_err());
+        assert!(RepoUrl::parse("https://bitbucket.org/vendor/sdk/pull-requests/7").is_err());
+    }
+
+    #[test]
+    fn test_src_urls() {
+        let repo = "https://api.bitbucket.org/2.0/repositories/w/r";
+        let url = src_dir_url(repo, "abc123", "docs/read me");
+        assert_eq!(url, "https://api.bitbucket.org/2.0/repositories/w/r/src/abc123/docs/read%20me/");
+        assert_eq!(split_src_url(&url), Some((repo.to_string(), "abc123".to_string())));
+        assert_eq!(src_dir_url(repo, "abc123", ""), format!("{}/src/abc123/", repo));
+
+        let entry = SrcEntry {
+            kind: "commit_file".to_string(),
+            path: "docs/a.txt".to_string(),
+            size: Some(3),
+        };
+        let item = entry.into_item(repo, "abc123").unwrap();
+        assert_eq!(item.name, "a.txt");
+        assert_eq!(item.download_url.as_deref(), Some(&*format!("{}/src/abc123/docs/a.txt", repo)));
+    }
+}
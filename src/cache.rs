@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::CacheConfig;
+
+// Leads every entry's file name; bump it when the key or entry format changes, and old
+// entries just age out
+const KEY_VERSION: &str = "v1";
+
+// Response bodies keyed by request URL and credential, revalidated with ETag / Last-Modified.
+// Best effort throughout: a cache that can't be read or written just misses.
+pub struct HttpCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    // Size of the directory as of the last scan plus what this process wrote since
+    total: Mutex<u64>,
+}
+
+pub struct CacheEntry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: Vec<u8>,
+}
+
+// First line of every entry file; the body follows it verbatim
+#[derive(Serialize, Deserialize)]
+struct EntryHeader {
+    url: String,
+    scope: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl HttpCache {
+    pub fn open(config: &CacheConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => dirs::cache_dir()?.join("ghgrab").join("http"),
+        };
+        // bodies of private repositories end up here, so the directory is owner-only
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+            builder.mode(0o700);
+            if dir.exists() {
+                fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).ok()?;
+            }
+        }
+        builder.create(&dir).ok()?;
+        let total = dir_size(&dir);
+        Some(HttpCache {
+            dir,
+            max_bytes: config.max_size_mb * 1024 * 1024,
+            total: Mutex::new(total),
+        })
+    }
+
+    fn entry_path(&self, url: &str, scope: &str) -> PathBuf {
+        self.dir.join(format!("{}-{}", KEY_VERSION, digest(&[scope, url])))
+    }
+
+    // `scope` tells apart the credentials a response was fetched with; see `scope`
+    pub fn get(&self, url: &str, scope: &str) -> Option<CacheEntry> {
+        let data = fs::read(self.entry_path(url, scope)).ok()?;
+        let split = data.iter().position(|b| *b == b'\n')?;
+        let header: EntryHeader = serde_json::from_slice(&data[..split]).ok()?;
+        // hash collisions are harmless as long as the key is checked
+        if header.url != url || header.scope != scope {
+            return None;
+        }
+        Some(CacheEntry {
+            etag: header.etag,
+            last_modified: header.last_modified,
+            body: data[split + 1..].to_vec(),
+        })
+    }
+
+    // Only responses carrying a validator are worth keeping; nothing else can be revalidated
+    pub fn put(&self, url: &str, scope: &str, etag: Option<&str>, last_modified: Option<&str>, body: &[u8]) {
+        if etag.is_none() && last_modified.is_none() {
+            return;
+        }
+        let header = EntryHeader {
+            url: url.to_string(),
+            scope: scope.to_string(),
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
+        };
+        let Ok(mut data) = serde_json::to_vec(&header) else {
+            return;
+        };
+        data.push(b'\n');
+        data.extend_from_slice(body);
+
+        // write to a temporary file first so a concurrent reader never sees half an entry
+        let path = self.entry_path(url, scope);
+        let tmp = path.with_extension("tmp");
+        let mut options = fs::File::options();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let replaced = fs::metadata(&path).map_or(0, |meta| meta.len());
+        let written = options.open(&tmp).and_then(|mut f| f.write_all(&data));
+        if written.is_err() || fs::rename(&tmp, &path).is_err() {
+            let _ = fs::remove_file(&tmp);
+            return;
+        }
+
+        // only scan the directory once it has outgrown its limit
+        let mut total = self.total.lock().unwrap();
+        *total = (*total + data.len() as u64).saturating_sub(replaced);
+        if *total > self.max_bytes {
+            *total = self.evict();
+        }
+    }
+
+    // Mark an entry as recently used so eviction keeps it
+    pub fn touch(&self, url: &str, scope: &str) {
+        if let Ok(file) = fs::File::options().append(true).open(self.entry_path(url, scope)) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+    }
+
+    // Drop least recently used entries until the directory fits in `max_bytes`; the size left
+    fn evict(&self) -> u64 {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return 0;
+        };
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = dir
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                meta.is_file()
+                    .then(|| (meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), e.path()))
+            })
+            .collect();
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        if total <= self.max_bytes {
+            return total;
+        }
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= len;
+            }
+        }
+        total
+    }
+}
+
+// Cache scope for requests sent with `token`: a hash, so that one account's responses are
+// never served to another and the token itself never reaches the disk
+pub fn scope(token: Option<&str>) -> String {
+    let Some(token) = token else {
+        return String::new();
+    };
+    digest(&[token])
+}
+
+// SHA-256 of `parts`, so keys stay the same from one build of ghgrab to the next
+fn digest(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // length-prefixed, so ("ab", "c") and ("a", "bc") differ
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.finalize()[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir).map_or(0, |entries| {
+        entries
+            .filter_map(|e| e.ok()?.metadata().ok())
+            .filter(|meta| meta.is_file())
+            .map(|meta| meta.len())
+            .sum()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_roundtrip_and_eviction() {
+        let dir = std::env::temp_dir().join(format!("ghgrab-cache-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = HttpCache::open(&CacheConfig {
+            enabled: true,
+            dir: Some(dir.clone()),
+            max_size_mb: 1,
+        })
+        .unwrap();
+
+        cache.put("https://a", "", Some("\"v1\""), None, b"first");
+        cache.put("https://b", "", None, None, b"no validator");
+        let entry = cache.get("https://a", "").unwrap();
+        assert_eq!(entry.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(entry.body, b"first");
+        assert!(cache.get("https://b", "").is_none());
+
+        // what one token fetched is not served to another or to anonymous requests
+        let alice = scope(Some("token-a"));
+        // keys must not change between builds, or every upgrade empties the cache
+        assert_eq!(alice, "93fc675f3dad50c4922dbe88433aad5c");
+        cache.put("https://private", &alice, Some("p"), None, b"secret");
+        assert!(cache.get("https://private", &alice).is_some());
+        assert!(cache.get("https://private", &scope(Some("token-b"))).is_none());
+        assert!(cache.get("https://private", &scope(None)).is_none());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&dir), 0o700);
+            assert_eq!(mode(&cache.entry_path("https://private", &alice)), 0o600);
+        }
+
+        // two 600 KiB bodies can't both fit in 1 MiB; the older one goes
+        let big = vec![b'x'; 600 * 1024];
+        cache.put("https://big1", "", Some("1"), None, &big);
+        // writes within one clock tick share a timestamp; make the order unambiguous
+        let an_hour_ago = SystemTime::now() - std::time::Duration::from_secs(3600);
+        let big1 = fs::File::options().append(true).open(cache.entry_path("https://big1", "")).unwrap();
+        big1.set_modified(an_hour_ago).unwrap();
+        cache.put("https://big2", "", Some("2"), None, &big);
+        assert!(cache.get("https://big2", "").is_some());
+        assert!(cache.get("https://big1", "").is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
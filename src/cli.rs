@@ -0,0 +1,296 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::auth::{CredentialSource, CredentialStore, DeviceFlow};
+use crate::config;
+use crate::download::Downloader;
+use crate::github::GitHubClient;
+use crate::provider::{ChangeStatus, Host, Provider, Providers, RepoUrl, Target};
+
+#[derive(Debug, PartialEq)]
+pub enum AuthCommand {
+    Login { hostname: Option<String> },
+    Status,
+    Logout { hostname: Option<String> },
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    // `ghgrab auth ...` instead of a download
+    pub auth: Option<AuthCommand>,
+    pub url: Option<String>,
+    pub out: Option<PathBuf>,
+    // Write only the permalink's `#L` range instead of whole files
+    pub lines: bool,
+    // Comparisons and pull requests: also write the unified diff / removed paths
+    pub patch: bool,
+    pub removed_list: bool,
+    // Sleep through an exhausted rate limit rather than failing
+    pub wait_for_reset: bool,
+    // Skip the on-disk HTTP cache for this run
+    pub no_cache: bool,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+
+        if args.peek().map(String::as_str) == Some("auth") {
+            args.next();
+            parsed.auth = Some(AuthCommand::parse(args)?);
+            return Ok(parsed);
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--out" => {
+                    let dir = args
+                        .next()
+                        .ok_or_else(|| anyhow!("{} expects a directory", arg))?;
+                    parsed.out = Some(PathBuf::from(dir));
+                }
+                "--lines" => parsed.lines = true,
+                "--patch" => parsed.patch = true,
+                "--removed-list" => parsed.removed_list = true,
+                "--wait-for-reset" => parsed.wait_for_reset = true,
+                "--no-cache" => parsed.no_cache = true,
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(anyhow!("Unknown option: {}", flag));
+                }
+                _ if parsed.url.is_none() => parsed.url = Some(arg),
+                _ => return Err(anyhow!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl AuthCommand {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let command = args
+            .next()
+            .ok_or_else(|| anyhow!("auth expects one of: login, status, logout"))?;
+
+        let mut hostname = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--hostname" => {
+                    hostname = Some(
+                        args.next()
+                            .ok_or_else(|| anyhow!("{} expects a host name", arg))?,
+                    );
+                }
+                _ => return Err(anyhow!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        match command.as_str() {
+            "login" => Ok(AuthCommand::Login { hostname }),
+            "logout" => Ok(AuthCommand::Logout { hostname }),
+            "status" if hostname.is_none() => Ok(AuthCommand::Status),
+            "status" => Err(anyhow!("auth status does not take --hostname")),
+            other => Err(anyhow!("Unknown auth command: {}", other)),
+        }
+    }
+}
+
+fn find_host(hostname: Option<&str>) -> Result<Host> {
+    let name = hostname.unwrap_or("github.com");
+    config::known_hosts()
+        .into_iter()
+        .find(|host| host.name() == name)
+        .ok_or_else(|| anyhow!("Unknown host {}; add it under [[hosts]] in the config first", name))
+}
+
+pub async fn run_auth(command: AuthCommand) -> Result<()> {
+    match command {
+        AuthCommand::Login { hostname } => {
+            let host = find_host(hostname.as_deref())?;
+            let oauth = &config::get().oauth;
+            let client_id = oauth
+                .client_id
+                .as_deref()
+                .ok_or_else(|| anyhow!("No OAuth app configured; set client_id under [oauth] in the config"))?;
+            let endpoint = oauth.endpoint.as_deref().unwrap_or(&host.web);
+
+            let flow = DeviceFlow::new(endpoint, client_id)?;
+            let code = flow.request_code("repo").await?;
+            eprintln!("First copy your one-time code: {}", code.user_code);
+            eprintln!("Then open {} in your browser and enter it.", code.verification_uri);
+            eprintln!("Waiting for approval...");
+            let token = flow.poll_token(&code).await?;
+
+            let mut store = CredentialStore::load()?;
+            store.hosts.insert(host.name().to_string(), token);
+            let path = store.save()?;
+            eprintln!("Logged in to {}. Token saved to {}", host.name(), path.display());
+        }
+        AuthCommand::Status => {
+            let client = GitHubClient::new()?;
+            for host in config::known_hosts() {
+                let Some(source) = client.credential_source(&host) else {
+                    eprintln!("{}: not logged in", host.name());
+                    continue;
+                };
+                if let CredentialSource::App(_) = source {
+                    // installation tokens have no user behind them
+                    match client.credential(&host).await {
+                        Ok(_) => eprintln!("{}: authenticated as {}", host.name(), source),
+                        Err(err) => eprintln!("{}: {}", host.name(), err),
+                    }
+                    continue;
+                }
+                match client.fetch_login(&host).await {
+                    Ok(login) => eprintln!("{}: logged in as {} ({})", host.name(), login, source),
+                    Err(err) => eprintln!("{}: {}", host.name(), err),
+                }
+            }
+        }
+        AuthCommand::Logout { hostname } => {
+            let host = find_host(hostname.as_deref())?;
+            let mut store = CredentialStore::load()?;
+            if store.hosts.remove(host.name()).is_none() {
+                return Err(anyhow!("Not logged in to {} with ghgrab", host.name()));
+            }
+            store.save()?;
+            eprintln!("Logged out of {}", host.name());
+        }
+    }
+    Ok(())
+}
+
+fn report_credentials(provider: &dyn Provider, url: &RepoUrl) {
+    match provider.credential_source(&url.host) {
+        Some(source) => eprintln!("Using credentials from {}", source),
+        None => eprintln!("No credentials found for {}, using anonymous access", url.host.name()),
+    }
+}
+
+// Headless mode: grab whatever the URL points at straight into `out`
+pub async fn run_download(url_str: &str, out: PathBuf, args: &Args) -> Result<()> {
+    let mut repo_url = RepoUrl::parse(url_str)?;
+    // only a set of changes has a patch or removed files; say so before downloading anything
+    if !matches!(repo_url.target, Target::Compare { .. } | Target::PullRequest(_)) {
+        if args.patch {
+            return Err(anyhow!("--patch needs a compare or pull request URL"));
+        }
+        if args.removed_list {
+            return Err(anyhow!("--removed-list needs a compare or pull request URL"));
+        }
+    }
+    let providers = Arc::new(Providers::new()?);
+    let provider = providers.for_host(&repo_url.host);
+    provider.set_progress(Arc::new(|msg| eprintln!("{}", msg)));
+    report_credentials(provider, &repo_url);
+    provider.resolve_ref(&mut repo_url).await?;
+
+    let listing_url = repo_url.dir_url();
+    let listing = provider.list_dir(&listing_url).await?;
+    if listing.partial {
+        let view = match listing_url.target {
+            Target::Compare { .. } => "the comparison's changed files",
+            _ if listing_url.path.is_empty() => "the repository root",
+            _ => &listing_url.path,
+        };
+        eprintln!(
+            "Warning: {} returned only part of {}; some files may be missing",
+            repo_url.host.name(),
+            view
+        );
+    }
+    let mut items = listing.items;
+
+    if repo_url.is_file() {
+        items.retain(|i| i.path == repo_url.path);
+        if items.is_empty() {
+            return Err(anyhow!("File not found in repository: {}", repo_url.path));
+        }
+    }
+
+    provider.resolve_lfs(&mut items, &repo_url).await;
+    for item in items.iter_mut() {
+        item.selected = true;
+    }
+
+    let downloader = Downloader::new(providers.clone(), out.clone())?;
+    let errors = downloader
+        .download_items(&items, &repo_url, |msg| eprintln!("{}", msg))
+        .await?;
+
+    for err in &errors {
+        eprintln!("{}", err);
+    }
+
+    if args.removed_list {
+        let removed: String = items
+            .iter()
+            .filter(|i| i.change == Some(ChangeStatus::Removed))
+            .map(|i| format!("{}\n", i.path))
+            .collect();
+        let path = out.join("removed-files.txt");
+        fs::write(&path, removed).context(format!("Failed to write file: {:?}", path))?;
+        eprintln!("Wrote {}", path.display());
+    }
+
+    if args.patch {
+        let diff = provider.fetch_diff(&repo_url).await?;
+        let path = out.join("changes.patch");
+        fs::write(&path, diff).context(format!("Failed to write file: {:?}", path))?;
+        eprintln!("Wrote {}", path.display());
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Completed with {} errors", errors.len()))
+    }
+}
+
+// Write the `#Lx-Ly` range of a permalink to `out`, or stdout when no file is given
+pub async fn run_lines(url_str: &str, out: Option<PathBuf>) -> Result<()> {
+    let mut repo_url = RepoUrl::parse(url_str)?;
+    let providers = Providers::new()?;
+    let provider = providers.for_host(&repo_url.host);
+    provider.set_progress(Arc::new(|msg| eprintln!("{}", msg)));
+    report_credentials(provider, &repo_url);
+    provider.resolve_ref(&mut repo_url).await?;
+
+    // some hosts' links only say whether they point at a file once resolved
+    if !repo_url.is_file() {
+        return Err(anyhow!("--lines needs a link to a file"));
+    }
+    let range = repo_url
+        .lines
+        .ok_or_else(|| anyhow!("URL has no #L<start>-L<end> line anchor"))?;
+
+    let listing = provider.list_dir(&repo_url.dir_url()).await?;
+    let item = listing
+        .items
+        .into_iter()
+        .find(|i| i.path == repo_url.path)
+        .ok_or_else(|| anyhow!("File not found in repository: {}", repo_url.path))?;
+    let download_url = item
+        .download_url
+        .context("No download URL for file")?;
+    let content = provider.raw_file(&download_url).await?;
+    let content = String::from_utf8(content).context("File is not valid UTF-8 text")?;
+    let snippet = range.extract(&content);
+
+    let source = repo_url.lines_url(range);
+
+    match out {
+        Some(path) => {
+            fs::write(&path, snippet).context(format!("Failed to write file: {:?}", path))?;
+            eprintln!("Wrote {} from {}", path.display(), source);
+        }
+        None => {
+            print!("{}", snippet);
+            eprintln!("Source: {}", source);
+        }
+    }
+    Ok(())
+}
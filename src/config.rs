@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::provider::{Host, HostKind};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub hosts: Vec<Host>,
+    pub oauth: OAuthConfig,
+    pub app: AppConfig,
+    // Pause until the rate limit resets instead of failing
+    pub wait_for_reset: bool,
+    pub retry: RetryConfig,
+    pub cache: CacheConfig,
+    pub network: NetworkConfig,
+}
+
+// Proxy, extra trust roots and timeouts for the shared HTTP client; see `http::client`
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    // e.g. http://proxy.corp:3128; HTTPS_PROXY / NO_PROXY apply when unset
+    pub proxy: Option<String>,
+    pub proxy_username: Option<String>,
+    pub proxy_password: Option<String>,
+    // Comma-separated hosts that bypass `proxy`; NO_PROXY when unset
+    pub no_proxy: Option<String>,
+    // PEM files with extra root certificates, trusted alongside the system roots
+    pub ca_certs: Vec<PathBuf>,
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            proxy: None,
+            proxy_username: None,
+            proxy_password: None,
+            no_proxy: None,
+            ca_certs: Vec::new(),
+            connect_timeout_secs: 30,
+            read_timeout_secs: 60,
+        }
+    }
+}
+
+// Hand-written so the proxy password never ends up in debug output
+impl std::fmt::Debug for NetworkConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkConfig")
+            .field("proxy", &self.proxy)
+            .field("proxy_username", &self.proxy_username)
+            .field("proxy_password", &self.proxy_password.as_ref().map(|_| "<redacted>"))
+            .field("no_proxy", &self.no_proxy)
+            .field("ca_certs", &self.ca_certs)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
+            .field("read_timeout_secs", &self.read_timeout_secs)
+            .finish()
+    }
+}
+
+// On-disk HTTP cache; see `cache::HttpCache`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    // Defaults to `<user cache dir>/ghgrab/http`
+    pub dir: Option<PathBuf>,
+    pub max_size_mb: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            dir: None,
+            max_size_mb: 100,
+        }
+    }
+}
+
+// Retries for idempotent requests that fail on the network or with a 5xx
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    // Total tries including the first; 1 disables retrying
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 4,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn base_delay(&self) -> Duration {
+        Duration::from_millis(self.base_delay_ms)
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        Duration::from_millis(self.max_delay_ms)
+    }
+}
+
+// GitHub App credentials for unattended use; see `auth::AppAuth`
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct AppConfig {
+    pub id: Option<u64>,
+    pub installation_id: Option<u64>,
+    // Path to the PEM key, or the PEM text itself
+    pub private_key: Option<String>,
+    // Instance the app is installed on; github.com when unset
+    pub host: Option<String>,
+}
+
+// OAuth app used by `ghgrab auth login`
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct OAuthConfig {
+    pub client_id: Option<String>,
+    // Base of the `/login/device/code` endpoints; defaults to the host's web URL.
+    // Plain http:// works, which is how the flow is exercised against a local stand-in.
+    pub endpoint: Option<String>,
+}
+
+// On-disk layout of config.toml
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    hosts: Vec<HostEntry>,
+    #[serde(default)]
+    oauth: OAuthConfig,
+    #[serde(default)]
+    app: AppConfig,
+    #[serde(default)]
+    wait_for_reset: bool,
+    #[serde(default)]
+    retry: RetryConfig,
+    #[serde(default)]
+    cache: CacheConfig,
+    #[serde(default)]
+    network: NetworkConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct HostEntry {
+    // "github" (the default), "gitlab", "gitea" or "forgejo"; Bitbucket Cloud is the only
+    // Bitbucket supported and needs no entry
+    #[serde(default)]
+    kind: HostKind,
+    web: String,
+    api: Option<String>,
+    lfs: Option<String>,
+}
+
+impl Config {
+    // GHGRAB_CONFIG wins over the per-user config directory
+    pub fn path() -> Option<PathBuf> {
+        if let Ok(path) = env::var("GHGRAB_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        dirs::config_dir().map(|dir| dir.join("ghgrab").join("config.toml"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Config::default());
+        };
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let text = fs::read_to_string(&path)
+            .context(format!("Failed to read config: {:?}", path))?;
+        Self::from_toml(&text).context(format!("Invalid config: {:?}", path))
+    }
+
+    fn from_toml(text: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(text)?;
+        let hosts = file
+            .hosts
+            .into_iter()
+            .map(|h| Host::self_hosted(h.kind, &h.web, h.api.as_deref(), h.lfs.as_deref()))
+            .collect();
+        Ok(Config {
+            hosts,
+            oauth: file.oauth,
+            app: file.app,
+            wait_for_reset: file.wait_for_reset,
+            retry: RetryConfig {
+                max_attempts: file.retry.max_attempts.max(1),
+                ..file.retry
+            },
+            cache: file.cache,
+            network: file.network,
+        })
+    }
+}
+
+// GitHub instances ghgrab can log in to: github.com plus every configured one
+pub fn known_hosts() -> Vec<Host> {
+    std::iter::once(Host::github())
+        .chain(get().hosts.iter().filter(|h| h.kind == HostKind::GitHub).cloned())
+        .collect()
+}
+
+// Install the loaded config, after command-line overrides have been applied
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
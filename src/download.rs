@@ -1,55 +1,89 @@
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::PathBuf;
-use crate::github::{GitHubClient, RepoItem};
+use std::sync::Arc;
+use crate::provider::{ChangeStatus, Progress, Provider, Providers, RepoItem, RepoUrl};
 
 pub struct Downloader {
-    client: GitHubClient,
+    // the session's clients, so credentials, caches and rate-limit state carry over
+    providers: Arc<Providers>,
     base_path: PathBuf,
 }
 
 impl Downloader {
-    pub fn new(base_path: PathBuf) -> Result<Self> {
+    pub fn new(providers: Arc<Providers>, base_path: PathBuf) -> Result<Self> {
         fs::create_dir_all(&base_path)?;
-        Ok(Downloader {
-            client: GitHubClient::new()?,
-            base_path,
-        })
+        Ok(Downloader { providers, base_path })
     }
 
     pub async fn download_items(
         &self,
         items: &[RepoItem],
-        _repo_path: &str, 
+        url: &RepoUrl,
         progress_callback: impl Fn(String) + Send + Sync + 'static,
     ) -> Result<Vec<String>> {
         let mut errors = Vec::new();
-        
+        // the provider reports rate-limit waits through the same callback
+        let provider = self.providers.for_host(&url.host);
+        let progress_callback: Progress = Arc::new(progress_callback);
+        provider.set_progress(progress_callback.clone());
+        let progress_callback = &*progress_callback;
+
+        // expand folders into their files first, so the total is known before the first byte
+        let mut files: Vec<(RepoItem, PathBuf)> = Vec::new();
         for item in items {
-            if !item.selected {
+            // nothing exists on the head side of a removed file
+            if !item.selected || item.change == Some(ChangeStatus::Removed) {
                 continue;
             }
 
             let dest_path = self.base_path.join(&item.name);
+            if item.is_file() {
+                files.push((item.clone(), dest_path));
+                continue;
+            }
+
+            progress_callback(format!("Scanning folder: {}", item.name));
+            match self.folder_files(provider, item).await {
+                Ok(contents) => {
+                    fs::create_dir_all(&dest_path)?;
+                    files.extend(contents.into_iter().map(|(file, relative)| (file, dest_path.join(relative))));
+                }
+                Err(e) => errors.push(format!("Failed to download {}: {}", item.name, e)),
+            }
+        }
 
-            let result = if item.is_file() {
-                self.download_file(item, dest_path, &progress_callback).await
-            } else {
-                self.download_folder(item, dest_path, &progress_callback).await
-            };
+        let total: u64 = files.iter().filter_map(|(file, _)| file.actual_size()).sum();
+        progress_callback(format!("Downloading {} files ({})", files.len(), format_size(total)));
 
-            if let Err(e) = result {
-                errors.push(format!("Failed to download {}: {}", item.name, e));
+        // one failed file doesn't stop the rest of its folder
+        for (file, dest_path) in &files {
+            if let Err(e) = self.download_file(provider, file, dest_path.clone(), progress_callback).await {
+                errors.push(format!("Failed to download {}: {}", file.path, e));
             }
         }
         Ok(errors)
     }
 
+    // Files below a folder item (or a release's assets) with their paths relative to it
+    async fn folder_files(&self, provider: &dyn Provider, item: &RepoItem) -> Result<Vec<(RepoItem, PathBuf)>> {
+        let prefix = format!("{}/", item.path);
+        let files = provider.walk_tree(item).await?;
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                let relative = PathBuf::from(file.path.strip_prefix(&prefix).unwrap_or(&file.name));
+                (file, relative)
+            })
+            .collect())
+    }
+
     async fn download_file(
         &self,
+        provider: &dyn Provider,
         item: &RepoItem,
         dest_path: PathBuf,
-        progress_callback: &(impl Fn(String) + Send + Sync),
+        progress_callback: &(dyn Fn(String) + Send + Sync),
     ) -> Result<()> {
         let download_url = item
             .actual_download_url()
@@ -58,14 +92,7 @@ impl Downloader {
         let lfs_indicator = if item.is_lfs() { " [LFS]" } else { "" };
         progress_callback(format!("Downloading{}: {}", lfs_indicator, item.name));
 
-        let response = reqwest::get(download_url)
-            .await
-            .context("Failed to download file")?;
-        
-        let content = response
-            .bytes()
-            .await
-            .context("Failed to read file content")?;
+        let content = provider.raw_file(download_url).await?;
 
         if let Some(parent) = dest_path.parent() {
             fs::create_dir_all(parent)?;
@@ -76,29 +103,16 @@ impl Downloader {
 
         Ok(())
     }
+}
 
-    fn download_folder<'a>(
-        &'a self,
-        item: &'a RepoItem,
-        dest_path: PathBuf,
-        progress_callback: &'a (impl Fn(String) + Send + Sync),
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
-        Box::pin(async move {
-            progress_callback(format!("Scanning folder: {}", item.name));
-            
-            fs::create_dir_all(&dest_path)?;
-            let contents = self.client.fetch_contents(&item.url).await?;
-
-            for sub_item in contents {
-                let sub_dest_path = dest_path.join(&sub_item.name);
-                
-                if sub_item.is_file() {
-                    self.download_file(&sub_item, sub_dest_path, progress_callback).await?;
-                } else {
-                    self.download_folder(&sub_item, sub_dest_path, progress_callback).await?;
-                }
-            }
-            Ok(())
-        })
+pub fn format_size(size: u64) -> String {
+    if size < 1024 {
+        format!("{} B", size)
+    } else if size < 1024 * 1024 {
+        format!("{:.1} KB", size as f64 / 1024.0)
+    } else if size < 1024 * 1024 * 1024 {
+        format!("{:.1} MB", size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
     }
 }
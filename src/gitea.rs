@@ -0,0 +1,361 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::header::AUTHORIZATION;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::auth::CredentialSource;
+use crate::http;
+use crate::provider::{
+    found, resolve_lfs_pointers, resolve_repo_ref, split_contents_url, Backend, Host, HostKind, LineRange, Listing, Progress, Provider, RefCache,
+    RefKind, RefLookup, RefNames, RepoItem, RepoUrl, Target,
+};
+use crate::rest::{encode, encode_path, RestClient};
+
+// Gitea's default cap on list endpoints
+const PAGE_LIMIT: usize = 50;
+// Branch and tag lists stop after this many pages
+const MAX_PAGES: usize = 100;
+
+pub const BACKEND: Backend = Backend {
+    kind: HostKind::Gitea,
+    new_client: || Ok(Box::new(GiteaClient::new()?)),
+    parse_web,
+    parse_public: |url, path_segments| {
+        (url.host_str() == Some("codeberg.org")).then(|| parse_web(Host::codeberg(), path_segments))
+    },
+    // the web UI names the ref's kind; a SHA is always a valid commit
+    blob: "src/commit",
+    line_anchor: LineRange::anchor,
+    // `src` links only turn out to be files once resolved
+    anchor_on_dirs: true,
+};
+
+// <web>/<owner>/<repo>[/src|raw|media/branch|tag|commit/<ref>/<path>]
+pub fn parse_web(host: Host, path_segments: &[&str]) -> Result<RepoUrl> {
+    let [owner, repo, rest @ ..] = path_segments else {
+        return Err(anyhow!("URL must contain owner and repository"));
+    };
+
+    let (reference, path, target) = match rest {
+        [] => (None, String::new(), Target::Dir),
+        // `src` shows files and folders alike; resolve_ref tells them apart
+        [view @ ("src" | "raw" | "media"), "branch" | "tag" | "commit", reference, path @ ..] => {
+            let path = path.join("/");
+            let target = if *view != "src" && !path.is_empty() {
+                Target::File
+            } else {
+                Target::Dir
+            };
+            (Some(reference.to_string()), path, target)
+        }
+        [view, ..] => return Err(anyhow!("Gitea /{} links are not supported", view)),
+    };
+
+    Ok(RepoUrl {
+        host,
+        owner: owner.to_string(),
+        repo: repo.strip_suffix(".git").unwrap_or(repo).to_string(),
+        // `branch/feature/x/src` could be branch `feature` or branch `feature/x`
+        ambiguous_ref: !path.is_empty(),
+        reference,
+        pinned: None,
+        path,
+        target,
+        lines: None,
+    })
+}
+
+fn repo_api_url(url: &RepoUrl) -> String {
+    format!("{}/repos/{}/{}", url.host.api, encode(&url.owner), encode(&url.repo))
+}
+
+fn contents_url(repo: &str, path: &str, reference: &str) -> String {
+    let path = if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", encode_path(path))
+    };
+    format!("{}/contents{}?ref={}", repo, path, encode(reference))
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoInfo {
+    default_branch: String,
+    #[serde(default)]
+    empty: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitInfo {
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BranchInfo {
+    commit: BranchCommit,
+}
+
+#[derive(Debug, Deserialize)]
+struct BranchCommit {
+    id: String,
+}
+
+// Annotated or not, a tag names the commit it points at
+#[derive(Debug, Deserialize)]
+struct TagInfo {
+    commit: CommitInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefName {
+    name: String,
+}
+
+// The contents endpoint answers a folder with a list and a file with a single entry
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Contents {
+    Dir(Vec<RepoItem>),
+    File(serde::de::IgnoredAny),
+}
+
+#[derive(Debug, Deserialize)]
+struct TreePage {
+    #[serde(default)]
+    tree: Vec<TreeEntry>,
+    #[serde(default)]
+    total_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct TreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    size: Option<u64>,
+    sha: String,
+}
+
+pub struct GiteaClient {
+    rest: RestClient,
+    ref_cache: RefCache,
+}
+
+impl GiteaClient {
+    pub fn new() -> Result<Self> {
+        Ok(GiteaClient {
+            rest: RestClient::new(Host::codeberg(), |token| (AUTHORIZATION, format!("token {}", token)))?,
+            ref_cache: RefCache::default(),
+        })
+    }
+
+    // Page through a list until `X-Total-Count` is reached; instances may cap `limit` lower,
+    // so the page size alone can't tell when a list ends
+    async fn get_pages<T: DeserializeOwned>(&self, url: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        for page in 1..=MAX_PAGES {
+            let response = self.rest.send(self.rest.get(&format!("{}&page={}", url, page))).await?;
+            let total: Option<usize> = response
+                .headers()
+                .get("x-total-count")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok());
+            let body = http::read_body(response).await?;
+            let batch: Vec<T> = serde_json::from_slice(&body).context("Failed to parse Gitea API response")?;
+            let empty = batch.is_empty();
+            items.extend(batch);
+            if empty || total.is_none_or(|total| items.len() >= total) {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    // Raw file or the LFS object it points to, at a fixed ref
+    fn media_url(repo: &str, path: &str, reference: &str) -> String {
+        format!("{}/media/{}?ref={}", repo, encode_path(path), encode(reference))
+    }
+}
+
+#[async_trait]
+impl RefLookup for GiteaClient {
+    fn ref_cache(&self) -> &RefCache {
+        &self.ref_cache
+    }
+
+    async fn default_branch(&self, url: &RepoUrl) -> Result<String> {
+        let info: RepoInfo = self.rest.get_json(&repo_api_url(url)).await?;
+        if info.empty {
+            return Err(anyhow!("Repository is empty"));
+        }
+        Ok(info.default_branch)
+    }
+
+    async fn fetch_ref_names(&self, url: &RepoUrl) -> Result<RefNames> {
+        let repo = repo_api_url(url);
+        let mut names = RefNames::default();
+        for kind in ["branches", "tags"] {
+            let refs: Vec<RefName> = self.get_pages(&format!("{}/{}?limit={}", repo, kind, PAGE_LIMIT)).await?;
+            let found = refs.into_iter().map(|r| r.name);
+            match kind {
+                "branches" => names.branches.extend(found),
+                _ => names.tags.extend(found),
+            }
+        }
+        Ok(names)
+    }
+
+    async fn ref_sha(&self, url: &RepoUrl, kind: RefKind, name: &str) -> Result<Option<String>> {
+        let repo = repo_api_url(url);
+        let sha = match kind {
+            RefKind::Branch => {
+                let lookup = format!("{}/branches/{}", repo, encode_path(name));
+                found(self.rest.get_json::<BranchInfo>(&lookup).await)?.map(|branch| branch.commit.id)
+            }
+            RefKind::Tag => {
+                let lookup = format!("{}/tags/{}", repo, encode_path(name));
+                found(self.rest.get_json::<TagInfo>(&lookup).await)?.map(|tag| tag.commit.sha)
+            }
+            RefKind::Commit => {
+                let lookup = format!(
+                    "{}/commits?sha={}&limit=1&stat=false&verification=false&files=false",
+                    repo,
+                    encode(name)
+                );
+                let commits = found(self.rest.get_json::<Vec<CommitInfo>>(&lookup).await)?;
+                commits.and_then(|commits| commits.into_iter().next()).map(|commit| commit.sha)
+            }
+        };
+        Ok(sha)
+    }
+
+    // `src` links show files and folders alike
+    async fn is_file(&self, url: &RepoUrl) -> Result<bool> {
+        let contents_url = contents_url(&repo_api_url(url), &url.path, &commit_of(url));
+        let contents: Contents = self.rest.get_json(&contents_url).await?;
+        Ok(matches!(contents, Contents::File(_)))
+    }
+}
+
+#[async_trait]
+impl Provider for GiteaClient {
+    fn set_progress(&self, progress: Progress) {
+        self.rest.set_progress(progress);
+    }
+
+    fn credential_source(&self, host: &Host) -> Option<CredentialSource> {
+        self.rest.credential_source(host)
+    }
+
+    async fn resolve_ref(&self, url: &mut RepoUrl) -> Result<()> {
+        resolve_repo_ref(self, url).await
+    }
+
+    async fn list_dir(&self, url: &RepoUrl) -> Result<Listing> {
+        let repo = repo_api_url(url);
+        match self.rest.get_json(&contents_url(&repo, &url.path, &commit_of(url))).await? {
+            Contents::Dir(items) => Ok(items.into()),
+            Contents::File(_) => Err(anyhow!("{} is a file, not a folder", url.path)),
+        }
+    }
+
+    async fn walk_tree(&self, dir: &RepoItem) -> Result<Vec<RepoItem>> {
+        let (repo, reference) = match split_contents_url(&dir.url) {
+            Some((repo, Some(reference))) => (repo, reference),
+            _ => return Err(anyhow!("Not a repository folder: {}", dir.url)),
+        };
+        let sha = dir.sha.as_deref().ok_or_else(|| anyhow!("Folder {} has no tree SHA", dir.name))?;
+
+        let mut entries = Vec::new();
+        for page in 1.. {
+            let tree: TreePage = self
+                .rest
+                .get_json(&format!("{}/git/trees/{}?recursive=true&page={}", repo, sha, page))
+                .await?;
+            let done = tree.tree.is_empty() || entries.len() + tree.tree.len() >= tree.total_count;
+            entries.extend(tree.tree);
+            if done {
+                break;
+            }
+        }
+
+        // tree paths are relative to the folder walked
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.kind == "blob")
+            .map(|entry| {
+                let path = format!("{}/{}", dir.path, entry.path);
+                RepoItem {
+                    name: entry.path.rsplit('/').next().unwrap_or_default().to_string(),
+                    item_type: "file".to_string(),
+                    download_url: Some(Self::media_url(&repo, &path, &reference)),
+                    url: format!("{}/contents/{}?ref={}", repo, encode_path(&path), encode(&reference)),
+                    size: entry.size,
+                    sha: Some(entry.sha),
+                    path,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+
+    async fn raw_file(&self, url: &str) -> Result<Vec<u8>> {
+        self.rest.download(url).await
+    }
+
+    async fn resolve_lfs(&self, items: &mut [RepoItem], url: &RepoUrl) {
+        // the media endpoint serves the object too, just without the batch API's redirect
+        let repo = repo_api_url(url);
+        let reference = commit_of(url);
+        resolve_lfs_pointers(&self.rest, items, url, |item| Some(Self::media_url(&repo, &item.path, &reference))).await;
+    }
+}
+
+fn commit_of(url: &RepoUrl) -> String {
+    url.commitish().unwrap_or("HEAD").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::HostKind;
+
+    #[test]
+    fn test_parse_gitea_urls() {
+        let parsed = RepoUrl::parse("https://codeberg.org/forgejo/forgejo/src/branch/forgejo/docs/api").unwrap();
+        assert_eq!(parsed.host.kind, HostKind::Gitea);
+        assert_eq!(parsed.host.api, "https://codeberg.org/api/v1");
+        assert_eq!((parsed.owner.as_str(), parsed.repo.as_str()), ("forgejo", "forgejo"));
+        assert_eq!(parsed.reference.as_deref(), Some("forgejo"));
+        assert_eq!(parsed.path, "docs/api");
+        assert_eq!(parsed.target, Target::Dir);
+        assert!(parsed.ambiguous_ref);
+
+        let parsed = RepoUrl::parse("https://codeberg.org/owner/repo/raw/tag/v1.2/README.md").unwrap();
+        assert!(parsed.is_file());
+
+        let parsed = RepoUrl::parse("git@codeberg.org:owner/repo.git").unwrap();
+        assert_eq!(parsed.repo, "repo");
+        assert_eq!(parsed.reference, None);
+
+        let hosts = vec![Host::self_hosted(HostKind::Gitea, "https://git.example.com", None, None)];
+        let parsed = RepoUrl::parse_with_hosts("https://git.example.com/team/tool/src/commit/abc123", &hosts).unwrap();
+        assert_eq!(repo_api_url(&parsed), "https://git.example.com/api/v1/repos/team/tool");
+        assert!(!parsed.ambiguous_ref);
+
+        assert!(RepoUrl::parse("https://codeberg.org/owner").is_err());
+        assert!(RepoUrl::parse("https://codeberg.org/owner/repo/issues/3").is_err());
+    }
+
+    #[test]
+    fn test_contents_urls() {
+        let repo = "https://codeberg.org/api/v1/repos/o/r";
+        assert_eq!(contents_url(repo, "", "main"), "https://codeberg.org/api/v1/repos/o/r/contents?ref=main");
+        let url = contents_url(repo, "docs/read me", "feature/x");
+        assert_eq!(url, "https://codeberg.org/api/v1/repos/o/r/contents/docs/read%20me?ref=feature%2Fx");
+        let (split_repo, reference) = split_contents_url(&url).unwrap();
+        assert_eq!(split_repo, repo);
+        assert_eq!(reference.as_deref(), Some("feature/x"));
+    }
+}
@@ -1,325 +1,1428 @@
 use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
 use url::Url;
 
-#[derive(Debug, Clone)]
-pub struct GitHubUrl {
-    pub owner: String,
-    pub repo: String,
-    pub branch: String,
-    pub path: String,
+use crate::auth::{self, AppAuth, Credential, CredentialCache, CredentialSource};
+use crate::cache::{self, CacheEntry, HttpCache};
+use crate::config;
+use crate::http::{self, Api};
+use crate::provider::{
+    found, is_clone_scheme, resolve_lfs_pointers, resolve_repo_ref, split_contents_url, Backend, ChangeStatus, Host,
+    HostKind, LfsProbe, LineRange, Listing, PinnedRef, Progress, Provider, RateLimit, RefCache, RefKind, RefLookup,
+    RefNames, RepoItem, RepoUrl, Target,
+};
+
+// `base...head`, or the two-dot form GitHub also accepts
+fn split_compare(range: &str) -> Option<(String, String)> {
+    let (base, head) = range.split_once("...").or_else(|| range.split_once(".."))?;
+    if base.is_empty() || head.is_empty() {
+        return None;
+    }
+    Some((base.to_string(), head.to_string()))
+}
+
+pub const BACKEND: Backend = Backend {
+    kind: HostKind::GitHub,
+    new_client: || Ok(Box::new(GitHubClient::new()?)),
+    parse_web,
+    parse_public: parse_url,
+    blob: "blob",
+    line_anchor: LineRange::anchor,
+    anchor_on_dirs: false,
+};
+
+// github.com, its gist/raw/media hosts; None when the URL belongs to none of them
+pub fn parse_url(url: &Url, path_segments: &[&str]) -> Option<Result<RepoUrl>> {
+    let parsed = match url.host_str() {
+        Some("github.com") | Some("www.github.com") => parse_web(Host::github(), path_segments),
+        _ if is_clone_scheme(url.scheme()) => return None,
+        Some("gist.github.com") | Some("gist.githubusercontent.com") => parse_gist(path_segments),
+        Some("raw.githubusercontent.com") => parse_raw(path_segments),
+        Some("media.githubusercontent.com") => match path_segments.split_first() {
+            Some((&"media", rest)) => parse_raw(rest),
+            _ => Err(anyhow!("Media URL must start with /media/")),
+        },
+        _ => return None,
+    };
+    Some(parsed)
 }
 
-impl GitHubUrl {
-    pub fn parse(url_str: &str) -> Result<Self> {
-        let url = Url::parse(url_str).context("Invalid URL format")?;
-        
-        if url.host_str() != Some("github.com") {
-            return Err(anyhow!("Not a GitHub URL"));
+// <web>/<owner>/<repo>[/tree|blob|raw/<ref>/<path>]
+pub fn parse_web(host: Host, path_segments: &[&str]) -> Result<RepoUrl> {
+    if path_segments.len() < 2 {
+        return Err(anyhow!("URL must contain owner and repository"));
+    }
+
+    let owner = path_segments[0].to_string();
+    let repo = path_segments[1];
+    let repo = repo.strip_suffix(".git").unwrap_or(repo).to_string();
+
+    let kind = path_segments.get(2).copied();
+    let ambiguous_ref = path_segments.len() > 4 && matches!(kind, Some("tree") | Some("blob") | Some("raw"));
+    let (branch, path, target) = if path_segments.len() >= 4 && matches!(kind, Some("tree") | Some("blob") | Some("raw")) {
+        let branch = Some(path_segments[3].to_string());
+        let path = path_segments[4..].join("/");
+        let target = if kind != Some("tree") && !path.is_empty() {
+            Target::File
+        } else {
+            Target::Dir
+        };
+        (branch, path, target)
+    } else if kind == Some("releases") {
+        match &path_segments[3..] {
+            [] => (None, String::new(), Target::Releases),
+            ["latest"] => (None, String::new(), Target::Release),
+            ["tag", tag @ ..] if !tag.is_empty() => (Some(tag.join("/")), String::new(), Target::Release),
+            _ => return Err(anyhow!("Unrecognised releases URL")),
         }
+    } else if kind == Some("compare") {
+        let range = path_segments[3..].join("/");
+        let (base, head) = split_compare(&range)
+            .ok_or_else(|| anyhow!("Compare URL must look like /compare/<base>...<head>"))?;
+        (Some(head), String::new(), Target::Compare { base })
+    } else if kind == Some("pull") {
+        let number = path_segments
+            .get(3)
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| anyhow!("Invalid pull request number in URL"))?;
+        (None, String::new(), Target::PullRequest(number))
+    } else {
+        (None, String::new(), Target::Dir)
+    };
 
-        let path_segments: Vec<&str> = url
-            .path_segments()
-            .ok_or_else(|| anyhow!("Invalid URL path"))?
-            .collect();
+    Ok(RepoUrl {
+        host,
+        owner,
+        repo,
+        reference: branch,
+        pinned: None,
+        path,
+        target,
+        ambiguous_ref,
+        lines: None,
+    })
+}
+
+// gist.github.com/[<user>/]<id>[/<revision>], and raw links <user>/<id>/raw/[<revision>/]<file>
+fn parse_gist(path_segments: &[&str]) -> Result<RepoUrl> {
+    let (owner, id, revision) = match path_segments {
+        [id] => ("", *id, None),
+        [user, id] => (*user, *id, None),
+        [user, id, "raw", rest @ ..] => match rest {
+            [revision, _file, ..] => (*user, *id, Some(revision.to_string())),
+            _ => (*user, *id, None),
+        },
+        [user, id, revision, ..] => (*user, *id, Some(revision.to_string())),
+        [] => return Err(anyhow!("Gist URL must contain a gist id")),
+    };
+
+    Ok(RepoUrl {
+        host: Host::github(),
+        owner: owner.to_string(),
+        repo: id.to_string(),
+        reference: revision,
+        pinned: None,
+        path: String::new(),
+        target: Target::Gist,
+        ambiguous_ref: false,
+        lines: None,
+    })
+}
+
+// Compact form typed by hand: owner/repo[@ref][:path]
+pub fn parse_spec(spec: &str) -> Result<RepoUrl> {
+    let (repo_part, path) = match spec.split_once(':') {
+        Some((_, "")) => return Err(anyhow!("Empty path after ':' in '{}'", spec)),
+        Some((repo_part, path)) => (repo_part, path.trim_matches('/').to_string()),
+        None => (spec, String::new()),
+    };
 
-        if path_segments.len() < 2 {
-            return Err(anyhow!("URL must contain owner and repository"));
+    let (name, branch) = match repo_part.split_once('@') {
+        Some((_, "")) => return Err(anyhow!("Empty ref after '@' in '{}'", spec)),
+        Some((name, branch)) => (name, Some(branch.to_string())),
+        None => (repo_part, None),
+    };
+
+    // owner/repo@base...head[:path] compares two refs
+    let (branch, target) = match branch.as_deref().filter(|b| b.contains("..")) {
+        Some(range) => {
+            let (base, head) = split_compare(range)
+                .ok_or_else(|| anyhow!("Invalid ref range '{}' in '{}', expected base...head", range, spec))?;
+            (Some(head), Target::Compare { base })
         }
+        None => (branch, Target::Dir),
+    };
 
-        let owner = path_segments[0].to_string();
-        let repo = path_segments[1].to_string();
+    let (owner, repo) = name
+        .split_once('/')
+        .ok_or_else(|| anyhow!("Expected owner/repo but got '{}'", name))?;
 
-        let (branch, path) = if path_segments.len() >= 4 && path_segments[2] == "tree" {
-            let branch = path_segments[3].to_string();
-            let path = if path_segments.len() > 4 {
-                path_segments[4..].join("/")
-            } else {
-                String::new()
-            };
-            (branch, path)
-        } else {
-            ("main".to_string(), String::new())
+    if owner.is_empty() || !owner.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(anyhow!("Invalid owner '{}' in '{}'", owner, spec));
+    }
+    if repo.is_empty() || !repo.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return Err(anyhow!("Invalid repository name '{}' in '{}'", repo, spec));
+    }
+
+    Ok(RepoUrl {
+        host: Host::github(),
+        owner: owner.to_string(),
+        repo: repo.to_string(),
+        reference: branch,
+        pinned: None,
+        path,
+        target,
+        ambiguous_ref: false,
+        lines: None,
+    })
+}
+
+// <owner>/<repo>/[refs/heads|tags/]<ref>/<path>, as served by the raw and LFS media hosts
+fn parse_raw(path_segments: &[&str]) -> Result<RepoUrl> {
+    if path_segments.len() < 2 {
+        return Err(anyhow!("URL must contain owner and repository"));
+    }
+
+    let rest = match &path_segments[2..] {
+        ["refs", "heads" | "tags", rest @ ..] => rest,
+        rest => rest,
+    };
+
+    let ambiguous_ref = rest.len() > 2;
+    let (branch, path) = match rest.split_first() {
+        Some((branch, path)) if !path.is_empty() => (Some(branch.to_string()), path.join("/")),
+        _ => return Err(anyhow!("Raw URL must contain a ref and a file path")),
+    };
+
+    Ok(RepoUrl {
+        host: Host::github(),
+        owner: path_segments[0].to_string(),
+        repo: path_segments[1].to_string(),
+        reference: branch,
+        pinned: None,
+        path,
+        target: Target::File,
+        ambiguous_ref,
+        lines: None,
+    })
+}
+
+impl RepoUrl {
+    pub fn repo_api_url(&self) -> String {
+        format!("{}/repos/{}/{}", self.host.api, self.owner, self.repo)
+    }
+
+    pub fn compare_api_url(&self) -> String {
+        let base = match &self.target {
+            Target::Compare { base } => base.as_str(),
+            _ => "",
         };
+        format!(
+            "{}/compare/{}...{}",
+            self.repo_api_url(),
+            base,
+            self.commitish().unwrap_or_default()
+        )
+    }
 
-        Ok(GitHubUrl {
-            owner,
-            repo,
-            branch,
-            path,
-        })
+    pub fn gist_api_url(&self) -> String {
+        match self.commitish() {
+            Some(revision) => format!("{}/gists/{}/{}", self.host.api, self.repo, revision),
+            None => format!("{}/gists/{}", self.host.api, self.repo),
+        }
     }
 
     pub fn api_url(&self) -> String {
-        let base = format!(
-            "https://api.github.com/repos/{}/{}/contents",
-            self.owner, self.repo
-        );
+        let base = format!("{}/contents", self.repo_api_url());
+        let query = match self.commitish() {
+            Some(commitish) => format!("?ref={}", commitish),
+            None => String::new(),
+        };
         if self.path.is_empty() {
-            format!("{}?ref={}", base, self.branch)
+            format!("{}{}", base, query)
         } else {
-            format!("{}/{}?ref={}", base, self.path, self.branch)
+            format!("{}/{}{}", base, self.path, query)
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
-pub struct RepoItem {
-    pub name: String,
-    #[serde(rename = "type")]
-    pub item_type: String,
-    pub path: String,
-    pub download_url: Option<String>,
-    pub url: String,
-    #[allow(dead_code)]
-    pub size: Option<u64>, 
-    #[serde(skip)]
-    pub selected: bool,
-    #[serde(skip)]
-    pub lfs_oid: Option<String>,
-    #[serde(skip)]
-    pub lfs_size: Option<u64>,
-    #[serde(skip)]
-    pub lfs_download_url: Option<String>,
+// Keep only the `diff --git` sections whose new path is under `prefix`
+fn filter_diff(diff: &str, prefix: &str) -> String {
+    if prefix.is_empty() {
+        return diff.to_string();
+    }
+    let dir = format!("{}/", prefix);
+    let mut out = String::new();
+    let mut keep = false;
+    for line in diff.split_inclusive('\n') {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            let new_path = header
+                .trim_end()
+                .rsplit_once(" b/")
+                .map_or("", |(_, path)| path);
+            keep = new_path == prefix || new_path.starts_with(&dir);
+        }
+        if keep {
+            out.push_str(line);
+        }
+    }
+    out
 }
 
-impl RepoItem {
-    pub fn is_dir(&self) -> bool {
-        self.item_type == "dir"
-    }
+#[derive(Debug, Deserialize)]
+struct RepoInfo {
+    default_branch: String,
+}
 
-    pub fn is_file(&self) -> bool {
-        self.item_type == "file"
-    }
+#[derive(Debug, Deserialize)]
+struct GitRef {
+    #[serde(rename = "ref")]
+    name: String,
+}
 
-    pub fn is_lfs(&self) -> bool {
-        self.lfs_oid.is_some()
+// Status of a file in a pull request or comparison
+fn change_status(status: &str, previous: Option<String>) -> ChangeStatus {
+    match status {
+        "added" | "copied" => ChangeStatus::Added,
+        "removed" => ChangeStatus::Removed,
+        "renamed" => ChangeStatus::Renamed {
+            from: previous.unwrap_or_default(),
+        },
+        _ => ChangeStatus::Modified,
     }
+}
 
-    pub fn actual_size(&self) -> Option<u64> {
-        self.lfs_size.or(self.size)
-    }
+// Directory listings from the Contents API are capped at this many entries
+const CONTENTS_LIMIT: usize = 1000;
+// A comparison lists at most this many changed files, on its first page only, so paging
+// through it doesn't reveal the rest
+const COMPARE_FILES_LIMIT: usize = 300;
 
-    pub fn actual_download_url(&self) -> Option<&String> {
-        self.lfs_download_url.as_ref().or(self.download_url.as_ref())
-    }
+// `git/trees/<sha>` response; `truncated` is set when GitHub cut a recursive listing short
+#[derive(Debug, Deserialize)]
+struct TreeInfo {
+    tree: Vec<TreeEntry>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    sha: String,
+    size: Option<u64>,
 }
 
-#[derive(Debug, Clone)]
-pub struct LfsPointer {
-    pub oid: String,
-    pub size: u64,
+// Entry of a pull request's (or comparison's) `files` list
+#[derive(Debug, Deserialize)]
+struct ChangedFile {
+    filename: String,
+    status: String,
+    previous_filename: Option<String>,
+    raw_url: Option<String>,
+    contents_url: String,
 }
 
-impl LfsPointer {
-    pub fn parse(content: &str) -> Option<Self> {
-        if !content.starts_with("version https://git-lfs.github.com/spec/v1") {
-            return None;
+impl ChangedFile {
+    fn into_item(self) -> RepoItem {
+        let change = change_status(&self.status, self.previous_filename);
+        // a removed file has no head-side version to download
+        let download_url = match change {
+            ChangeStatus::Removed => None,
+            _ => self.raw_url,
+        };
+        RepoItem {
+            name: self.filename.clone(),
+            item_type: "file".to_string(),
+            path: self.filename,
+            download_url,
+            url: self.contents_url,
+            change: Some(change),
+            ..Default::default()
         }
+    }
+}
 
-        let mut oid = None;
-        let mut size = None;
+#[derive(Debug, Deserialize)]
+struct CompareInfo {
+    #[serde(default)]
+    files: Vec<ChangedFile>,
+}
 
-        for line in content.lines() {
-            if line.starts_with("oid sha256:") {    
-                oid = Some(line.trim_start_matches("oid sha256:").to_string());
-            } else if line.starts_with("size ") {
-                size = line.trim_start_matches("size ").parse().ok();
-            }
-        }
+#[derive(Debug, Deserialize)]
+struct GistInfo {
+    owner: Option<GistOwner>,
+    // sorted so the listing order is stable
+    files: BTreeMap<String, GistFile>,
+    #[serde(default)]
+    history: Vec<GistVersion>,
+}
 
-        match (oid, size) {
-            (Some(oid), Some(size)) => Some(LfsPointer { oid, size }),
-            _ => None,
+#[derive(Debug, Deserialize)]
+struct GistOwner {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GistVersion {
+    version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GistFile {
+    filename: String,
+    size: u64,
+    // always complete, unlike the inline `content` of files over 1 MB
+    raw_url: String,
+}
+
+impl GistFile {
+    fn into_item(self, gist_url: &str) -> RepoItem {
+        RepoItem {
+            name: self.filename.clone(),
+            item_type: "file".to_string(),
+            path: self.filename,
+            download_url: Some(self.raw_url),
+            url: gist_url.to_string(),
+            size: Some(self.size),
+            ..Default::default()
         }
     }
 }
 
-#[derive(Debug, serde::Serialize)]
-struct LfsBatchRequest {
-    operation: String,
-    transfers: Vec<String>,
-    objects: Vec<LfsObject>,
+#[derive(Debug, Deserialize)]
+struct ReleaseInfo {
+    url: String,
+    tag_name: String,
+    zipball_url: Option<String>,
+    tarball_url: Option<String>,
+    #[serde(default)]
+    assets: Vec<ReleaseAsset>,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
-struct LfsObject {
-    oid: String,
+#[derive(Debug, Deserialize)]
+struct ReleaseAsset {
+    name: String,
     size: u64,
+    download_count: u64,
+    content_type: String,
+    url: String,
+    browser_download_url: String,
+}
+
+impl ReleaseInfo {
+    fn into_list_item(self) -> RepoItem {
+        RepoItem {
+            name: self.tag_name.clone(),
+            item_type: "release".to_string(),
+            path: self.tag_name,
+            url: self.url,
+            ..Default::default()
+        }
+    }
+
+    // Uploaded assets followed by the auto-generated source archives
+    fn into_asset_items(self) -> Vec<RepoItem> {
+        let mut items: Vec<RepoItem> = self
+            .assets
+            .into_iter()
+            .map(|asset| RepoItem {
+                name: asset.name.clone(),
+                item_type: "file".to_string(),
+                path: asset.name,
+                download_url: Some(asset.browser_download_url),
+                url: asset.url,
+                size: Some(asset.size),
+                download_count: Some(asset.download_count),
+                content_type: Some(asset.content_type),
+                ..Default::default()
+            })
+            .collect();
+
+        let tag = self.tag_name.replace('/', "-");
+        let archives = [
+            (self.zipball_url, format!("source-{}.zip", tag)),
+            (self.tarball_url, format!("source-{}.tar.gz", tag)),
+        ];
+        for (archive_url, name) in archives {
+            if let Some(archive_url) = archive_url {
+                items.push(RepoItem {
+                    name: name.clone(),
+                    item_type: "file".to_string(),
+                    path: name,
+                    download_url: Some(archive_url.clone()),
+                    url: archive_url,
+                    ..Default::default()
+                });
+            }
+        }
+        items
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct LfsBatchResponse {
-    objects: Vec<LfsResponseObject>,
+#[derive(Debug, Deserialize)]
+struct PullRequestInfo {
+    head: PullRequestHead,
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct LfsResponseObject {
-    #[allow(dead_code)]
-    oid: String,
-    #[allow(dead_code)]
-    size: u64,
-    actions: Option<LfsActions>,
+#[derive(Debug, Deserialize)]
+struct PullRequestHead {
+    // "fork-owner:branch"
+    label: String,
+    sha: String,
+}
+
+// The quota belongs to the account on each instance, not to a client, so every client
+// reports into one place, keyed by host name
+static RATE_LIMIT: Mutex<BTreeMap<String, RateLimit>> = Mutex::new(BTreeMap::new());
+
+impl RateLimit {
+    // Quota from the `X-RateLimit-*` headers of an API response
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let number = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<u64>().ok();
+        let limit = number("x-ratelimit-limit")?;
+        let remaining = number("x-ratelimit-remaining")?;
+        Some(RateLimit {
+            limit,
+            remaining,
+            used: number("x-ratelimit-used").unwrap_or(limit.saturating_sub(remaining)),
+            reset: number("x-ratelimit-reset").unwrap_or(0),
+        })
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct LfsActions {
-    download: Option<LfsDownloadAction>,
+// `1h 02m`, `12m 03s`, `9s`
+pub fn format_wait(wait: Duration) -> String {
+    let secs = wait.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct LfsDownloadAction {
-    href: String,
+// Tell apart the reasons GitHub answers 403 (or 429): exhausted quota, secondary
+// (abuse) limits, SAML SSO enforcement and plain missing permissions
+fn forbidden_error(headers: &HeaderMap, body: &str) -> anyhow::Error {
+    let message = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("message")?.as_str().map(str::to_string))
+        .unwrap_or_default();
+
+    if let Some(sso) = headers.get("x-github-sso").and_then(|v| v.to_str().ok()) {
+        let url = sso.split_once("url=").map_or("", |(_, url)| url);
+        return anyhow!(
+            "This organization enforces SAML SSO; authorize your token for it first {}",
+            url
+        );
+    }
+    if let Some(limit) = RateLimit::from_headers(headers)
+        && limit.remaining == 0
+    {
+        return anyhow!(
+            "Rate limit exceeded ({} of {} requests used); resets in {}",
+            limit.used,
+            limit.limit,
+            format_wait(limit.resets_in())
+        );
+    }
+    if let Some(wait) = http::retry_after(headers) {
+        return anyhow!(
+            "Secondary rate limit hit ({}); retry in {}",
+            message,
+            format_wait(wait)
+        );
+    }
+    if message.to_lowercase().contains("rate limit") {
+        return anyhow!("Secondary rate limit hit: {}", message);
+    }
+    if message.is_empty() {
+        anyhow!("Access denied: your credentials lack permission for this resource")
+    } else {
+        anyhow!("Access denied: {}", message)
+    }
 }
 
 pub struct GitHubClient {
     client: reqwest::Client,
+    ref_cache: RefCache,
+    credentials: CredentialCache,
+    // Takes precedence over discovered tokens on the host it is installed on
+    app: Option<AppAuth>,
+    // Where waits (and later retries) are reported; set by whoever drives a download
+    progress: Mutex<Option<Progress>>,
+    // None when caching is turned off
+    cache: Option<HttpCache>,
 }
 
 impl GitHubClient {
     pub fn new() -> Result<Self> {
-        let client = reqwest::Client::builder()
-            .user_agent("ghgrab/0.1.0")
-            .build()
-            .context("Failed to create HTTP client")?;
-        Ok(GitHubClient { client })
+        Ok(GitHubClient {
+            client: http::client()?,
+            ref_cache: RefCache::default(),
+            credentials: CredentialCache::default(),
+            app: AppAuth::load(&config::get().app)?,
+            progress: Mutex::new(None),
+            cache: HttpCache::open(&config::get().cache),
+        })
     }
 
-    pub async fn fetch_contents(&self, url: &str) -> Result<Vec<RepoItem>> {
-        let response = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .context("Failed to send request to GitHub API")?;
+    fn report(&self, message: String) {
+        if let Some(callback) = self.progress.lock().unwrap().as_ref() {
+            callback(message);
+        }
+    }
 
-        if response.status().as_u16() == 403 {
-            return Err(anyhow!("Rate limit exceeded. Please try again later."));
+    fn app_for(&self, host: &Host) -> Option<&AppAuth> {
+        self.app.as_ref().filter(|app| app.host == host.name())
+    }
+
+    pub async fn credential(&self, host: &Host) -> Result<Option<Credential>> {
+        match self.app_for(host) {
+            Some(app) => app.token(&self.client, &host.api).await.map(Some),
+            None => Ok(self.discovered_credential(host)),
         }
+    }
 
-        if response.status().as_u16() == 404 {
-            return Err(anyhow!("Path not found in repository"));
+    fn discovered_credential(&self, host: &Host) -> Option<Credential> {
+        self.credentials.get(host)
+    }
+
+    // Every file below a folder, found with one recursive Git Trees call. Very large trees come
+    // back truncated; those are walked one level per call instead.
+    async fn walk_git_tree(&self, dir: &RepoItem) -> Result<Vec<RepoItem>> {
+        let sha = dir.sha.as_deref().context("Folder listing has no tree SHA")?;
+        let (repo_api, reference) = split_contents_url(&dir.url)
+            .ok_or_else(|| anyhow!("Not a repository folder: {}", dir.url))?;
+        let reference = reference.unwrap_or_else(|| "HEAD".to_string());
+
+        let tree: TreeInfo = self
+            .get_json(&format!("{}/git/trees/{}?recursive=1", repo_api, sha))
+            .await?;
+        let entries = if tree.truncated {
+            self.report(format!("{} is too large for one tree call, walking it folder by folder", dir.name));
+            self.walk_tree_by_level(&repo_api, sha).await?
+        } else {
+            tree.tree
+        };
+
+        Ok(entries
+            .into_iter()
+            // submodules ("commit") have nothing to download
+            .filter(|entry| entry.kind == "blob")
+            .map(|entry| {
+                let path = format!("{}/{}", dir.path, entry.path);
+                RepoItem {
+                    name: path.rsplit('/').next().unwrap_or_default().to_string(),
+                    item_type: "file".to_string(),
+                    download_url: Self::raw_file_url(&repo_api, &reference, &path),
+                    url: format!("{}/contents/{}?ref={}", repo_api, path, reference),
+                    size: entry.size,
+                    sha: Some(entry.sha),
+                    path,
+                    ..Default::default()
+                }
+            })
+            .collect())
+    }
+
+    async fn walk_tree_by_level(&self, repo_api: &str, sha: &str) -> Result<Vec<TreeEntry>> {
+        let mut pending = vec![(String::new(), sha.to_string())];
+        let mut entries = Vec::new();
+        while let Some((prefix, sha)) = pending.pop() {
+            let tree: TreeInfo = self.get_json(&format!("{}/git/trees/{}", repo_api, sha)).await?;
+            for mut entry in tree.tree {
+                if !prefix.is_empty() {
+                    entry.path = format!("{}/{}", prefix, entry.path);
+                }
+                if entry.kind == "tree" {
+                    pending.push((entry.path.clone(), entry.sha.clone()));
+                }
+                entries.push(entry);
+            }
         }
+        Ok(entries)
+    }
 
-        if !response.status().is_success() {
-            return Err(anyhow!("GitHub API error: {}", response.status()));
+    // Where a file's bytes live outside the API, so downloads don't spend rate limit
+    fn raw_file_url(repo_api: &str, reference: &str, path: &str) -> Option<String> {
+        let api = Url::parse(repo_api).ok()?;
+        let mut segments: Vec<&str> = api.path_segments()?.collect();
+        let repo = segments.pop()?;
+        let owner = segments.pop()?;
+
+        let host = Self::host_for(&api)?;
+        let mut raw = if host.is_github_com() {
+            Url::parse(&format!("https://raw.githubusercontent.com/{}/{}", owner, repo)).ok()?
+        } else {
+            Url::parse(&format!("{}/{}/{}/raw", host.web, owner, repo)).ok()?
+        };
+        raw.path_segments_mut()
+            .ok()?
+            .push(reference)
+            .extend(path.split('/'));
+        Some(raw.to_string())
+    }
+
+    // Account the host's credential belongs to, which also proves the token works
+    pub async fn fetch_login(&self, host: &Host) -> Result<String> {
+        #[derive(Deserialize)]
+        struct User {
+            login: String,
         }
+        let user: User = self.get_json(&format!("{}/user", host.api)).await?;
+        Ok(user.login)
+    }
 
-        let items: Vec<RepoItem> = response
-            .json()
-            .await
-            .context("Failed to parse GitHub API response")?;
+    // Which instance a request goes to; tokens are only ever sent to their own instance
+    fn host_for(url: &Url) -> Option<Host> {
+        const ALIASES: [&str; 3] = [
+            "raw.githubusercontent.com",
+            "media.githubusercontent.com",
+            "gist.githubusercontent.com",
+        ];
+        Host::for_request(url, &Host::github(), &ALIASES)
+    }
+
+    pub async fn fetch_contents(&self, url: &str) -> Result<Vec<RepoItem>> {
+        let body = self.get_cached(url).await?;
+        serde_json::from_slice(&body).context("Failed to parse GitHub API response")
+    }
 
+    // Everything the URL's view shows: a folder's contents or a pull request's changed files
+    pub async fn fetch_listing(&self, url: &RepoUrl) -> Result<Listing> {
+        let items = match url.target {
+            Target::PullRequest(number) => self.fetch_pull_files(url, number).await,
+            Target::Releases => {
+                let mut items = Vec::new();
+                for page in 1..=10 {
+                    let releases_url = format!("{}/releases?per_page=100&page={}", url.repo_api_url(), page);
+                    let releases: Vec<ReleaseInfo> = self.get_json(&releases_url).await?;
+                    let done = releases.len() < 100;
+                    items.extend(releases.into_iter().map(ReleaseInfo::into_list_item));
+                    if done {
+                        break;
+                    }
+                }
+                Ok(items)
+            }
+            Target::Release => {
+                let release_url = match &url.reference {
+                    Some(tag) => format!("{}/releases/tags/{}", url.repo_api_url(), tag),
+                    None => format!("{}/releases/latest", url.repo_api_url()),
+                };
+                self.fetch_release_assets(&release_url).await
+            }
+            Target::Compare { .. } => {
+                let compare: CompareInfo = self.get_json(&url.compare_api_url()).await?;
+                let partial = compare.files.len() >= COMPARE_FILES_LIMIT;
+                let prefix = format!("{}/", url.path);
+                let items = compare
+                    .files
+                    .into_iter()
+                    .filter(|f| url.path.is_empty() || f.filename == url.path || f.filename.starts_with(&prefix))
+                    .map(ChangedFile::into_item)
+                    .collect();
+                return Ok(Listing { items, partial });
+            }
+            Target::Gist => {
+                let gist_url = url.gist_api_url();
+                let gist: GistInfo = self.get_json(&gist_url).await?;
+                Ok(gist.files.into_values().map(|f| f.into_item(&gist_url)).collect())
+            }
+            _ => return self.fetch_dir(url).await,
+        };
+        items.map(Listing::from)
+    }
+
+    // The Contents API stops at 1,000 entries without saying so. A full page may be
+    // missing entries, so the folder is listed again from its Git tree.
+    async fn fetch_dir(&self, url: &RepoUrl) -> Result<Listing> {
+        let items = self.fetch_contents(&url.api_url()).await?;
+        if items.len() < CONTENTS_LIMIT {
+            return Ok(items.into());
+        }
+
+        self.report(format!("More than {} entries, listing from the Git tree...", CONTENTS_LIMIT));
+        match self.fetch_dir_tree(url).await {
+            Ok(listing) => Ok(listing),
+            Err(_) => Ok(Listing { items, partial: true }),
+        }
+    }
+
+    async fn fetch_dir_tree(&self, url: &RepoUrl) -> Result<Listing> {
+        let repo_api = url.repo_api_url();
+        let reference = url.commitish().unwrap_or("HEAD");
+        // `<ref>:<path>` names the folder's tree without knowing its SHA
+        let tree_ish = if url.path.is_empty() {
+            reference.to_string()
+        } else {
+            format!("{}:{}", reference, url.path)
+        };
+        let tree: TreeInfo = self.get_json(&format!("{}/git/trees/{}", repo_api, tree_ish)).await?;
+
+        let items = tree
+            .tree
+            .into_iter()
+            .map(|entry| {
+                let path = if url.path.is_empty() {
+                    entry.path.clone()
+                } else {
+                    format!("{}/{}", url.path, entry.path)
+                };
+                let (item_type, download_url) = match entry.kind.as_str() {
+                    "tree" => ("dir", None),
+                    "commit" => ("submodule", None),
+                    _ => ("file", Self::raw_file_url(&repo_api, reference, &path)),
+                };
+                RepoItem {
+                    name: entry.path,
+                    item_type: item_type.to_string(),
+                    download_url,
+                    url: format!("{}/contents/{}?ref={}", repo_api, path, reference),
+                    size: entry.size,
+                    sha: Some(entry.sha),
+                    path,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        // a single tree level is only cut short past 100,000 entries
+        Ok(Listing {
+            items,
+            partial: tree.truncated,
+        })
+    }
+
+    async fn fetch_pull_files(&self, url: &RepoUrl, number: u64) -> Result<Vec<RepoItem>> {
+        let mut items = Vec::new();
+        // the API stops at 3000 files, 100 per page
+        for page in 1..=30 {
+            let files_url = format!(
+                "{}/pulls/{}/files?per_page=100&page={}",
+                url.repo_api_url(),
+                number,
+                page
+            );
+            let files: Vec<ChangedFile> = self.get_json(&files_url).await?;
+            let done = files.len() < 100;
+            items.extend(files.into_iter().map(ChangedFile::into_item));
+            if done {
+                break;
+            }
+        }
         Ok(items)
     }
 
-    // Fetch raw content 
-    pub async fn fetch_raw_content(&self, url: &str) -> Result<String> {
-        let response = self
+    pub async fn fetch_release_assets(&self, release_url: &str) -> Result<Vec<RepoItem>> {
+        let release: ReleaseInfo = self.get_json(release_url).await?;
+        Ok(release.into_asset_items())
+    }
+
+    // Accepts branches, tags and full or abbreviated SHAs
+    pub async fn fetch_commit_sha(&self, url: &RepoUrl, reference: &str) -> Result<String> {
+        let commit_url = format!("{}/commits/{}", url.repo_api_url(), reference);
+        let request = self
             .client
-            .get(url)
-            .send()
-            .await
-            .context("Failed to fetch raw content")?;
+            .get(&commit_url)
+            .header("Accept", "application/vnd.github.sha");
+        let response = self.send(request).await?;
+        let sha = http::read_body(response).await.context("Failed to read commit SHA")?;
+        Ok(String::from_utf8_lossy(&sha).trim().to_string())
+    }
+
+    // Fill in what the URL left open: the default branch, where a slashed ref like
+    // `feature/x` ends, and the commit the ref points at right now
+    pub async fn resolve_url(&self, url: &mut RepoUrl) -> Result<()> {
+        if let Target::PullRequest(number) = url.target {
+            // the head may live in a fork; its SHA is reachable from the base repo either way
+            let pr_url = format!("{}/pulls/{}", url.repo_api_url(), number);
+            let pr: PullRequestInfo = self.get_json(&pr_url).await?;
+            url.reference = Some(pr.head.label);
+            url.pinned = Some(PinnedRef {
+                kind: RefKind::Branch,
+                sha: pr.head.sha,
+            });
+            return Ok(());
+        }
 
-        if !response.status().is_success() {
-            return Err(anyhow!("Failed to fetch file content"));
+        match url.target {
+            Target::Releases => return Ok(()),
+            Target::Release => {
+                if url.reference.is_none() {
+                    let latest_url = format!("{}/releases/latest", url.repo_api_url());
+                    let latest: ReleaseInfo = self.get_json(&latest_url).await?;
+                    url.reference = Some(latest.tag_name);
+                }
+                url.pinned = Some(PinnedRef {
+                    kind: RefKind::Tag,
+                    sha: self.fetch_commit_sha(url, url.reference.as_deref().unwrap_or_default()).await?,
+                });
+                return Ok(());
+            }
+            _ => {}
         }
 
-        let content = response.text().await.context("Failed to read content")?;
-        Ok(content)
+        if url.target == Target::Gist {
+            let gist: GistInfo = self.get_json(&url.gist_api_url()).await?;
+            if let Some(owner) = gist.owner {
+                url.owner = owner.login;
+            }
+            let sha = match (&url.reference, gist.history.into_iter().next()) {
+                (Some(revision), _) => revision.clone(),
+                (None, Some(latest)) => latest.version,
+                (None, None) => return Ok(()),
+            };
+            url.pinned = Some(PinnedRef {
+                kind: RefKind::Commit,
+                sha,
+            });
+            return Ok(());
+        }
+
+        resolve_repo_ref(self, url).await
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let response = self.send(self.client.get(url)).await?;
+        let body = http::read_body(response).await?;
+        serde_json::from_slice(&body).context("Failed to parse GitHub API response")
     }
 
-    // Call LFS batch API 
-    pub async fn get_lfs_download_url(&self, owner: &str, repo: &str, oid: &str, size: u64) -> Result<String> {
-        let batch_url = format!("https://github.com/{}/{}.git/info/lfs/objects/batch", owner, repo);
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        http::send(self, request).await
+    }
+
+    // How long to pause before the request can succeed, if it was refused for quota
+    fn rate_limit_wait(headers: &HeaderMap) -> Option<Duration> {
+        if let Some(wait) = http::retry_after(headers) {
+            return Some(wait);
+        }
+        let limit = RateLimit::from_headers(headers)?;
+        (limit.remaining == 0).then(|| limit.resets_in() + Duration::from_secs(1))
+    }
+
+    async fn wait_for_reset(&self, wait: Duration) {
+        let until = tokio::time::Instant::now() + wait;
+        loop {
+            let left = until.saturating_duration_since(tokio::time::Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            self.report(format!("Rate limit reached, resuming in {}", format_wait(left)));
+            // tick every second near the end, every half minute before that
+            let step = if left > Duration::from_secs(60) { Duration::from_secs(30) } else { Duration::from_secs(1) };
+            tokio::time::sleep(step.min(left)).await;
+        }
+        self.report("Rate limit reset, resuming".to_string());
+    }
 
-        let request = LfsBatchRequest {
-            operation: "download".to_string(),
-            transfers: vec!["basic".to_string()],
-            objects: vec![LfsObject {
-                oid: oid.to_string(),
-                size,
-            }],
+    // A whole file body, with the same headers and error handling as API calls
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let (_, body) = self.get_body(url, None).await?;
+        Ok(body)
+    }
+
+    // GET through the on-disk cache: a stored ETag / Last-Modified turns the request into
+    // a conditional one, and a 304 (free against the rate limit) serves the stored body
+    async fn get_cached(&self, url: &str) -> Result<Vec<u8>> {
+        let Some(cache) = &self.cache else {
+            return self.download(url).await;
+        };
+        // entries are kept per credential: the same URL can show each token something else
+        let credential = match Url::parse(url).ok().and_then(|u| Self::host_for(&u)) {
+            Some(host) => self.credential(&host).await?,
+            None => None,
         };
+        let scope = cache::scope(credential.as_ref().map(|c| c.token.as_str()));
+        let entry = cache.get(url, &scope);
+        let (headers, body) = self.get_body(url, entry.as_ref()).await?;
 
-        let response = self
-            .client
-            .post(&batch_url)
-            .header("Accept", "application/vnd.git-lfs+json")
-            .header("Content-Type", "application/vnd.git-lfs+json")
-            .json(&request)
-            .send()
+        match (headers, entry) {
+            (None, Some(entry)) => {
+                cache.touch(url, &scope);
+                Ok(entry.body)
+            }
+            (Some(headers), _) => {
+                let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+                cache.put(url, &scope, header("etag"), header("last-modified"), &body);
+                Ok(body)
+            }
+            (None, None) => Err(anyhow!("Unexpected 304 for an uncached request")),
+        }
+    }
+
+    // Response headers and body; headers are None when `cached` is still current (304)
+    async fn get_body(&self, url: &str, cached: Option<&CacheEntry>) -> Result<(Option<HeaderMap>, Vec<u8>)> {
+        let mut request = self.client.get(url);
+        if let Some(entry) = cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header("If-None-Match", etag);
+            }
+            if let Some(modified) = &entry.last_modified {
+                request = request.header("If-Modified-Since", modified);
+            }
+        }
+        Ok(match http::fetch(self, request).await? {
+            Some((headers, body)) => (Some(headers), body),
+            None => (None, Vec::new()),
+        })
+    }
+
+    pub async fn resolve_lfs_files(&self, items: &mut [RepoItem], url: &RepoUrl) {
+        // only repository contents can hold LFS pointers
+        if matches!(url.target, Target::Gist | Target::Releases | Target::Release) {
+            return;
+        }
+        // github.com also serves LFS objects from its media host
+        let media = |item: &RepoItem| {
+            url.host.is_github_com().then(|| {
+                format!(
+                    "https://media.githubusercontent.com/media/{}/{}/{}/{}",
+                    url.owner,
+                    url.repo,
+                    url.commitish().unwrap_or("HEAD"),
+                    item.path
+                )
+            })
+        };
+        resolve_lfs_pointers(self, items, url, media).await;
+    }
+}
+
+#[async_trait]
+impl Api for GitHubClient {
+    async fn check_status(&self, response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status().as_u16();
+
+        if status == 403 || status == 429 {
+            let headers = response.headers().clone();
+            let body = response.text().await.unwrap_or_default();
+            return Err(forbidden_error(&headers, &body));
+        }
+
+        // a commit lookup for a name that is neither a ref nor a SHA is "unprocessable" instead
+        if status == 404 || (status == 422 && response.url().path().contains("/commits/")) {
+            return Err(http::NotFound.into());
+        }
+
+        // 304 only ever answers our own conditional requests
+        if !response.status().is_success() && status != 304 {
+            return Err(anyhow!("GitHub API error: {}", response.status()));
+        }
+
+        Ok(response)
+    }
+
+    // Attach the instance's credential and send; only a rejected token is treated as an error here
+    async fn execute(&self, mut request: reqwest::Request) -> Result<reqwest::Response> {
+        let host = Self::host_for(request.url());
+        let credential = match &host {
+            Some(host) => self.credential(host).await?,
+            None => None,
+        };
+        if let Some(credential) = &credential {
+            // the LFS batch endpoint speaks git's Basic auth, the API takes the token directly
+            let value = if request.url().path().ends_with("/info/lfs/objects/batch") {
+                format!("Basic {}", STANDARD.encode(format!("x-access-token:{}", credential.token)))
+            } else {
+                format!("token {}", credential.token)
+            };
+            auth::authorize(&mut request, AUTHORIZATION, &value)?;
+        }
+
+        let response = http::execute(&self.client, request)
             .await
-            .context("Failed to call LFS batch API")?;
+            .context("Failed to send request to GitHub API")?;
 
-        if !response.status().is_success() {
-            return Err(anyhow!("LFS batch API error: {}", response.status()));
+        if let Some(host) = &host {
+            if let Some(limit) = RateLimit::from_headers(response.headers()) {
+                RATE_LIMIT.lock().unwrap().insert(host.name().to_string(), limit);
+            }
         }
 
-        let batch_response: LfsBatchResponse = response.json().await.context("Failed to parse LFS response")?;
+        auth::check_rejected(response, credential.as_ref())
+    }
 
-        batch_response
-            .objects
-            .into_iter()
-            .next()
-            .and_then(|obj| obj.actions)
-            .and_then(|actions| actions.download)
-            .map(|download| download.href)
-            .ok_or_else(|| anyhow!("No download URL in LFS response"))
-    }
-
-    pub async fn resolve_lfs_files(&self, items: &mut Vec<RepoItem>, owner: &str, repo: &str) {
-        for item in items.iter_mut() {
-            if item.is_file() {
-                if let Some(size) = item.size {
-                    if size < 1024 {
-                        if let Some(download_url) = &item.download_url {
-                            if let Ok(content) = self.fetch_raw_content(download_url).await {
-                                if let Some(pointer) = LfsPointer::parse(&content) {
-                                    item.lfs_oid = Some(pointer.oid.clone());
-                                    item.lfs_size = Some(pointer.size);
-
-                                    if let Ok(lfs_url) = self.get_lfs_download_url(owner, repo, &pointer.oid, pointer.size).await {
-                                        item.lfs_download_url = Some(lfs_url);
-                                    } else {
-                                        let media_url = format!(
-                                            "https://media.githubusercontent.com/media/{}/{}/master/{}",
-                                            owner, repo, item.path
-                                        );
-                                        item.lfs_download_url = Some(media_url);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    fn report(&self, message: String) {
+        GitHubClient::report(self, message)
+    }
+
+    async fn wait_out_quota(&self, response: &reqwest::Response) -> bool {
+        let status = response.status().as_u16();
+        if (status == 403 || status == 429) && http::may_wait_for_reset() {
+            if let Some(wait) = Self::rate_limit_wait(response.headers()) {
+                self.wait_for_reset(wait).await;
+                return true;
             }
         }
+        false
+    }
+}
+
+// The pointer probe goes through the cache like any other raw file
+#[async_trait]
+impl LfsProbe for GitHubClient {
+    async fn probe(&self, url: &str) -> Result<Vec<u8>> {
+        self.get_cached(url).await
+    }
+}
+
+#[async_trait]
+impl RefLookup for GitHubClient {
+    fn ref_cache(&self) -> &RefCache {
+        &self.ref_cache
+    }
+
+    async fn default_branch(&self, url: &RepoUrl) -> Result<String> {
+        let info: RepoInfo = self.get_json(&url.repo_api_url()).await?;
+        Ok(info.default_branch)
+    }
+
+    async fn fetch_ref_names(&self, url: &RepoUrl) -> Result<RefNames> {
+        let mut names = RefNames::default();
+        for kind in ["heads", "tags"] {
+            let refs_url = format!("{}/git/matching-refs/{}", url.repo_api_url(), kind);
+            let refs: Vec<GitRef> = self.get_json(&refs_url).await?;
+            let prefix = format!("refs/{}/", kind);
+            let found = refs
+                .into_iter()
+                .filter_map(|r| r.name.strip_prefix(&prefix).map(str::to_string));
+            match kind {
+                "heads" => names.branches.extend(found),
+                _ => names.tags.extend(found),
+            }
+        }
+        Ok(names)
+    }
+
+    // the commits endpoint takes `heads/<branch>` and `tags/<tag>` to say which one is meant
+    async fn ref_sha(&self, url: &RepoUrl, kind: RefKind, name: &str) -> Result<Option<String>> {
+        let reference = match kind {
+            RefKind::Branch => format!("heads/{}", name),
+            RefKind::Tag => format!("tags/{}", name),
+            RefKind::Commit => name.to_string(),
+        };
+        found(self.fetch_commit_sha(url, &reference).await)
+    }
+}
+
+#[async_trait]
+impl Provider for GitHubClient {
+    fn set_progress(&self, progress: Progress) {
+        *self.progress.lock().unwrap() = Some(progress);
+    }
+
+    fn credential_source(&self, host: &Host) -> Option<CredentialSource> {
+        match self.app_for(host) {
+            Some(app) => Some(CredentialSource::App(app.app_id)),
+            None => self.discovered_credential(host).map(|c| c.source),
+        }
+    }
+
+    fn rate_limit(&self, host: &Host) -> Option<RateLimit> {
+        RATE_LIMIT.lock().unwrap().get(host.name()).copied()
+    }
+
+    async fn resolve_ref(&self, url: &mut RepoUrl) -> Result<()> {
+        self.resolve_url(url).await
+    }
+
+    async fn list_dir(&self, url: &RepoUrl) -> Result<Listing> {
+        self.fetch_listing(url).await
+    }
+
+    // A release "folder" holds its assets; everything else is a Git tree
+    async fn walk_tree(&self, dir: &RepoItem) -> Result<Vec<RepoItem>> {
+        if dir.is_release() {
+            self.fetch_release_assets(&dir.url).await
+        } else {
+            self.walk_git_tree(dir).await
+        }
+    }
+
+    async fn raw_file(&self, url: &str) -> Result<Vec<u8>> {
+        self.download(url).await
+    }
+
+    async fn resolve_lfs(&self, items: &mut [RepoItem], url: &RepoUrl) {
+        self.resolve_lfs_files(items, url).await
+    }
+
+    // Trimmed to files under the URL's path
+    async fn fetch_diff(&self, url: &RepoUrl) -> Result<String> {
+        let diff_url = match url.target {
+            Target::Compare { .. } => url.compare_api_url(),
+            Target::PullRequest(number) => format!("{}/pulls/{}", url.repo_api_url(), number),
+            _ => return Err(anyhow!("Only comparisons and pull requests have a patch")),
+        };
+        let request = self
+            .client
+            .get(&diff_url)
+            .header("Accept", "application/vnd.github.diff");
+        let diff = http::read_body(self.send(request).await?)
+            .await
+            .context("Failed to read diff")?;
+        let diff = String::from_utf8_lossy(&diff);
+        Ok(filter_diff(&diff, &url.path))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::provider::LineRange;
 
     #[test]
     fn test_parse_github_url() {
         let url = "https://github.com/rust-lang/rust/tree/master/src/tools";
-        let parsed = GitHubUrl::parse(url).unwrap();
+        let parsed = RepoUrl::parse(url).unwrap();
         assert_eq!(parsed.owner, "rust-lang");
         assert_eq!(parsed.repo, "rust");
-        assert_eq!(parsed.branch, "master");
+        assert_eq!(parsed.reference.as_deref(), Some("master"));
         assert_eq!(parsed.path, "src/tools");
     }
 
     #[test]
     fn test_parse_root_url() {
         let url = "https://github.com/rust-lang/rust";
-        let parsed = GitHubUrl::parse(url).unwrap();
+        let parsed = RepoUrl::parse(url).unwrap();
         assert_eq!(parsed.owner, "rust-lang");
         assert_eq!(parsed.repo, "rust");
-        assert_eq!(parsed.branch, "main");
+        assert_eq!(parsed.reference, None);
         assert_eq!(parsed.path, "");
     }
+
+    #[test]
+    fn test_parse_blob_url() {
+        let url = "https://github.com/rust-lang/rust/blob/master/src/tools/tidy/src/main.rs";
+        let parsed = RepoUrl::parse(url).unwrap();
+        assert_eq!(parsed.reference.as_deref(), Some("master"));
+        assert_eq!(parsed.path, "src/tools/tidy/src/main.rs");
+        assert!(parsed.is_file());
+
+        let dir = parsed.dir_url();
+        assert_eq!(dir.path, "src/tools/tidy/src");
+        assert!(!dir.is_file());
+    }
+
+    #[test]
+    fn test_parse_raw_urls() {
+        let parsed = RepoUrl::parse("https://raw.githubusercontent.com/owner/repo/v1.0/docs/guide.md").unwrap();
+        assert_eq!(parsed.owner, "owner");
+        assert_eq!(parsed.repo, "repo");
+        assert_eq!(parsed.reference.as_deref(), Some("v1.0"));
+        assert_eq!(parsed.path, "docs/guide.md");
+        assert!(parsed.is_file());
+
+        let parsed = RepoUrl::parse("https://raw.githubusercontent.com/owner/repo/refs/heads/main/a.txt").unwrap();
+        assert_eq!(parsed.reference.as_deref(), Some("main"));
+        assert_eq!(parsed.path, "a.txt");
+
+        let parsed = RepoUrl::parse("https://media.githubusercontent.com/media/owner/repo/main/assets/big.bin").unwrap();
+        assert_eq!(parsed.repo, "repo");
+        assert_eq!(parsed.path, "assets/big.bin");
+
+        assert!(RepoUrl::parse("https://raw.githubusercontent.com/owner/repo/main").is_err());
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let parsed = RepoUrl::parse("owner/repo").unwrap();
+        assert_eq!(parsed.owner, "owner");
+        assert_eq!(parsed.repo, "repo");
+        assert_eq!(parsed.reference, None);
+        assert_eq!(parsed.path, "");
+
+        let parsed = RepoUrl::parse("owner/repo.rs@v1.2.0:docs/api").unwrap();
+        assert_eq!(parsed.repo, "repo.rs");
+        assert_eq!(parsed.reference.as_deref(), Some("v1.2.0"));
+        assert_eq!(parsed.path, "docs/api");
+
+        let parsed = RepoUrl::parse("github.com/owner/repo/tree/dev/src").unwrap();
+        assert_eq!(parsed.reference.as_deref(), Some("dev"));
+        assert_eq!(parsed.path, "src");
+
+        let err = RepoUrl::parse("owner/repo@:docs").unwrap_err().to_string();
+        assert!(err.contains("Empty ref"));
+        let err = RepoUrl::parse("own er/repo").unwrap_err().to_string();
+        assert!(err.contains("Invalid owner 'own er'"));
+        assert!(RepoUrl::parse("owner").is_err());
+    }
+
+    #[test]
+    fn test_parse_enterprise_host() {
+        let hosts = vec![
+            Host::self_hosted(HostKind::GitHub, "https://ghe.example.com", None, None),
+            Host::self_hosted(HostKind::GitHub, "http://localhost:8080", Some("http://localhost:8080/api"), None),
+        ];
+
+        let parsed = RepoUrl::parse_with_hosts("https://ghe.example.com/team/tool/tree/main/src", &hosts).unwrap();
+        assert_eq!(parsed.host.api, "https://ghe.example.com/api/v3");
+        assert_eq!(parsed.api_url(), "https://ghe.example.com/api/v3/repos/team/tool/contents/src?ref=main");
+
+        let parsed = RepoUrl::parse_with_hosts("http://localhost:8080/team/tool", &hosts).unwrap();
+        assert_eq!(parsed.host.lfs, "http://localhost:8080");
+        assert_eq!(parsed.repo_api_url(), "http://localhost:8080/api/repos/team/tool");
+
+        assert!(RepoUrl::parse_with_hosts("http://localhost:9090/team/tool", &hosts).is_err());
+        // unknown until registered
+        assert!(RepoUrl::parse_with_hosts("https://ghe.example.com/team/tool", &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_clone_urls() {
+        for url in [
+            "git@github.com:owner/repo.git",
+            "ssh://git@github.com/owner/repo",
+            "https://github.com/owner/repo.git",
+            "git://github.com/owner/repo.git",
+        ] {
+            let parsed = RepoUrl::parse(url).unwrap();
+            assert_eq!(parsed.owner, "owner", "{}", url);
+            assert_eq!(parsed.repo, "repo", "{}", url);
+            assert!(parsed.host.is_github_com());
+        }
+
+        let hosts = vec![Host::self_hosted(HostKind::GitHub, "https://ghe.example.com", None, None)];
+        let parsed = RepoUrl::parse_with_hosts("git@ghe.example.com:team/tool.git", &hosts).unwrap();
+        assert_eq!(parsed.host.web, "https://ghe.example.com");
+        assert_eq!(parsed.repo, "tool");
+
+        // only one `.git` is the clone suffix
+        let parsed = RepoUrl::parse("https://github.com/owner/foo.git.git").unwrap();
+        assert_eq!(parsed.repo, "foo.git");
+
+        assert!(RepoUrl::parse_with_hosts("git@example.com:owner/repo.git", &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_line_anchor() {
+        let parsed = RepoUrl::parse("https://github.com/owner/repo/blob/abc123/src/main.rs#L10-L42").unwrap();
+        assert_eq!(parsed.path, "src/main.rs");
+        assert_eq!(parsed.lines, Some(LineRange { start: 10, end: 42 }));
+
+        let parsed = RepoUrl::parse("https://github.com/owner/repo/blob/main/a.rs#L7").unwrap();
+        assert_eq!(parsed.lines, Some(LineRange { start: 7, end: 7 }));
+    }
+
+    #[test]
+    fn test_parse_pull_request_url() {
+        let parsed = RepoUrl::parse("https://github.com/owner/repo/pull/123/files").unwrap();
+        assert_eq!(parsed.target, Target::PullRequest(123));
+        assert_eq!(parsed.reference, None);
+        assert!(RepoUrl::parse("https://github.com/owner/repo/pull/abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_gist_url() {
+        let parsed = RepoUrl::parse("https://gist.github.com/octocat/aa5a315d61ae9438b18d").unwrap();
+        assert_eq!(parsed.target, Target::Gist);
+        assert_eq!(parsed.owner, "octocat");
+        assert_eq!(parsed.repo, "aa5a315d61ae9438b18d");
+        assert_eq!(parsed.gist_api_url(), "https://api.github.com/gists/aa5a315d61ae9438b18d");
+
+        let parsed = RepoUrl::parse("https://gist.github.com/octocat/aa5a315d61ae9438b18d/57a7f021").unwrap();
+        assert_eq!(parsed.reference.as_deref(), Some("57a7f021"));
+        assert_eq!(parsed.gist_api_url(), "https://api.github.com/gists/aa5a315d61ae9438b18d/57a7f021");
+
+        let parsed =
+            RepoUrl::parse("https://gist.githubusercontent.com/octocat/aa5a315d61ae9438b18d/raw/57a7f021/hello.md")
+                .unwrap();
+        assert_eq!(parsed.repo, "aa5a315d61ae9438b18d");
+        assert_eq!(parsed.reference.as_deref(), Some("57a7f021"));
+
+        let parsed = RepoUrl::parse("https://gist.githubusercontent.com/octocat/aa5a315d61ae9438b18d/raw/hello.md").unwrap();
+        assert_eq!(parsed.reference, None);
+    }
+
+    #[test]
+    fn test_parse_release_urls() {
+        let parsed = RepoUrl::parse("https://github.com/owner/repo/releases").unwrap();
+        assert_eq!(parsed.target, Target::Releases);
+
+        let parsed = RepoUrl::parse("https://github.com/owner/repo/releases/latest").unwrap();
+        assert_eq!(parsed.target, Target::Release);
+        assert_eq!(parsed.reference, None);
+
+        let parsed = RepoUrl::parse("https://github.com/owner/repo/releases/tag/v1.2.0").unwrap();
+        assert_eq!(parsed.target, Target::Release);
+        assert_eq!(parsed.reference.as_deref(), Some("v1.2.0"));
+    }
+
+    #[test]
+    fn test_parse_compare() {
+        let parsed = RepoUrl::parse("https://github.com/owner/repo/compare/v1.0...release/1.1").unwrap();
+        assert_eq!(parsed.target, Target::Compare { base: "v1.0".to_string() });
+        assert_eq!(parsed.reference.as_deref(), Some("release/1.1"));
+
+        let parsed = RepoUrl::parse("owner/repo@v1.0...v1.1:docs").unwrap();
+        assert_eq!(parsed.target, Target::Compare { base: "v1.0".to_string() });
+        assert_eq!(parsed.reference.as_deref(), Some("v1.1"));
+        assert_eq!(parsed.path, "docs");
+        assert_eq!(parsed.compare_api_url(), "https://api.github.com/repos/owner/repo/compare/v1.0...v1.1");
+
+        assert!(RepoUrl::parse("owner/repo@v1.0...").is_err());
+    }
+
+    #[test]
+    fn test_filter_diff() {
+        let diff = "diff --git a/docs/a.md b/docs/a.md\n+x\ndiff --git a/src/b.rs b/src/b.rs\n+y\n";
+        assert_eq!(filter_diff(diff, "docs"), "diff --git a/docs/a.md b/docs/a.md\n+x\n");
+        assert_eq!(filter_diff(diff, ""), diff);
+    }
+
+    #[test]
+    fn test_forbidden_error() {
+        fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+            let mut map = HeaderMap::new();
+            for (name, value) in pairs {
+                map.insert(*name, reqwest::header::HeaderValue::from_str(value).unwrap());
+            }
+            map
+        }
+
+        let quota = headers(&[
+            ("x-ratelimit-limit", "60"),
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-used", "60"),
+            ("x-ratelimit-reset", "0"),
+        ]);
+        assert_eq!(
+            RateLimit::from_headers(&quota),
+            Some(RateLimit { limit: 60, remaining: 0, used: 60, reset: 0 })
+        );
+        assert!(forbidden_error(&quota, "{}").to_string().starts_with("Rate limit exceeded (60 of 60"));
+
+        let secondary = headers(&[("retry-after", "90")]);
+        let body = r#"{"message":"You have exceeded a secondary rate limit"}"#;
+        assert!(forbidden_error(&secondary, body).to_string().ends_with("retry in 1m 30s"));
+
+        let sso = headers(&[("x-github-sso", "required; url=https://github.com/orgs/acme/sso")]);
+        assert!(forbidden_error(&sso, "{}").to_string().contains("https://github.com/orgs/acme/sso"));
+
+        let body = r#"{"message":"Resource not accessible by integration"}"#;
+        assert_eq!(
+            forbidden_error(&HeaderMap::new(), body).to_string(),
+            "Access denied: Resource not accessible by integration"
+        );
+    }
+
+    #[test]
+    fn test_raw_file_url() {
+        assert_eq!(
+            GitHubClient::raw_file_url("https://api.github.com/repos/owner/repo", "abc123", "docs/api/read me.md").as_deref(),
+            Some("https://raw.githubusercontent.com/owner/repo/abc123/docs/api/read%20me.md")
+        );
+    }
 }
@@ -0,0 +1,338 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::header::HeaderName;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use url::Url;
+
+use crate::auth::CredentialSource;
+use crate::http;
+use crate::provider::{
+    found, resolve_repo_ref, Backend, Host, HostKind, LineRange, Listing, Progress, Provider, RefCache, RefKind, RefLookup,
+    RefNames, RepoItem, RepoUrl, Target,
+};
+use crate::rest::{encode, RestClient};
+
+// The API's largest page size
+const PER_PAGE: usize = 100;
+// A folder listing stops after this many pages and is marked partial
+const MAX_PAGES: usize = 100;
+
+pub const BACKEND: Backend = Backend {
+    kind: HostKind::GitLab,
+    new_client: || Ok(Box::new(GitLabClient::new()?)),
+    parse_web,
+    parse_public: |url, path_segments| {
+        (url.host_str() == Some("gitlab.com")).then(|| parse_web(Host::gitlab(), path_segments))
+    },
+    blob: "-/blob",
+    line_anchor: LineRange::anchor,
+    anchor_on_dirs: false,
+};
+
+// <web>/<namespace>/<project>[/-/tree|blob|raw/<ref>/<path>]; namespaces nest, e.g. group/subgroup
+pub fn parse_web(host: Host, path_segments: &[&str]) -> Result<RepoUrl> {
+    let (project, rest) = match path_segments.iter().position(|s| *s == "-") {
+        Some(idx) => (&path_segments[..idx], &path_segments[idx + 1..]),
+        None => (path_segments, &[][..]),
+    };
+    let Some((repo, namespace)) = project.split_last().filter(|(_, namespace)| !namespace.is_empty()) else {
+        return Err(anyhow!("URL must contain a namespace and project"));
+    };
+
+    let (reference, path, target) = match rest {
+        [] => (None, String::new(), Target::Dir),
+        [kind @ ("tree" | "blob" | "raw"), reference, path @ ..] => {
+            let path = path.join("/");
+            let target = if *kind != "tree" && !path.is_empty() {
+                Target::File
+            } else {
+                Target::Dir
+            };
+            (Some(reference.to_string()), path, target)
+        }
+        [kind, ..] => return Err(anyhow!("GitLab /-/{} links are not supported", kind)),
+    };
+
+    Ok(RepoUrl {
+        host,
+        owner: namespace.join("/"),
+        repo: repo.strip_suffix(".git").unwrap_or(repo).to_string(),
+        // as on GitHub, `feature/x/src` could be ref `feature` or ref `feature/x`
+        ambiguous_ref: !path.is_empty(),
+        reference,
+        pinned: None,
+        path,
+        target,
+        lines: None,
+    })
+}
+
+fn project_url(url: &RepoUrl) -> String {
+    format!("{}/projects/{}", url.host.api, encode(&format!("{}/{}", url.owner, url.repo)))
+}
+
+fn tree_url(project: &str, path: &str, reference: &str) -> String {
+    format!(
+        "{}/repository/tree?path={}&ref={}&per_page={}",
+        project,
+        encode(path),
+        encode(reference),
+        PER_PAGE
+    )
+}
+
+// `<project>/repository/tree?...&ref=<ref>` back into the project's API URL and the ref
+fn split_tree_url(url: &str) -> Option<(String, String)> {
+    let parsed = Url::parse(url).ok()?;
+    let (project, _) = url.split_once("/repository/tree")?;
+    let (_, reference) = parsed.query_pairs().find(|(key, _)| key == "ref")?;
+    Some((project.to_string(), reference.into_owned()))
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectInfo {
+    // None for a project without commits
+    default_branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitInfo {
+    id: String,
+}
+
+// A branch or tag, with the commit it points at
+#[derive(Debug, Deserialize)]
+struct RefInfo {
+    commit: CommitInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefName {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TreeEntry {
+    id: String,
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    path: String,
+}
+
+impl TreeEntry {
+    // The tree API has no sizes, so items carry none
+    fn into_item(self, project: &str, reference: &str) -> RepoItem {
+        let file_url = format!("{}/repository/files/{}", project, encode(&self.path));
+        let (item_type, url, download_url) = match self.kind.as_str() {
+            "tree" => ("dir", tree_url(project, &self.path, reference), None),
+            "commit" => ("submodule", String::new(), None),
+            // `lfs=true` serves the object in place of its pointer
+            _ => (
+                "file",
+                format!("{}?ref={}", file_url, encode(reference)),
+                Some(format!("{}/raw?ref={}&lfs=true", file_url, encode(reference))),
+            ),
+        };
+        RepoItem {
+            name: self.name,
+            item_type: item_type.to_string(),
+            path: self.path,
+            download_url,
+            url,
+            sha: Some(self.id),
+            ..Default::default()
+        }
+    }
+}
+
+pub struct GitLabClient {
+    rest: RestClient,
+    ref_cache: RefCache,
+}
+
+impl GitLabClient {
+    pub fn new() -> Result<Self> {
+        Ok(GitLabClient {
+            rest: RestClient::new(Host::gitlab(), |token| {
+                (HeaderName::from_static("private-token"), token.to_string())
+            })?,
+            ref_cache: RefCache::default(),
+        })
+    }
+
+    // Follow `X-Next-Page` through an offset-paginated list; true if `max_pages` cut it short
+    async fn get_pages<T: DeserializeOwned>(&self, url: &str, max_pages: usize) -> Result<(Vec<T>, bool)> {
+        let mut items = Vec::new();
+        let mut page = 1;
+        for _ in 0..max_pages {
+            let response = self.rest.send(self.rest.get(&format!("{}&page={}", url, page))).await?;
+            let next = response
+                .headers()
+                .get("x-next-page")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok());
+            let body = http::read_body(response).await?;
+            let batch: Vec<T> = serde_json::from_slice(&body).context("Failed to parse GitLab API response")?;
+            items.extend(batch);
+            match next {
+                Some(next) => page = next,
+                None => return Ok((items, false)),
+            }
+        }
+        Ok((items, true))
+    }
+}
+
+#[async_trait]
+impl RefLookup for GitLabClient {
+    fn ref_cache(&self) -> &RefCache {
+        &self.ref_cache
+    }
+
+    async fn default_branch(&self, url: &RepoUrl) -> Result<String> {
+        let info: ProjectInfo = self.rest.get_json(&project_url(url)).await?;
+        info.default_branch.ok_or_else(|| anyhow!("Repository is empty"))
+    }
+
+    async fn fetch_ref_names(&self, url: &RepoUrl) -> Result<RefNames> {
+        let project = project_url(url);
+        let mut names = RefNames::default();
+        for kind in ["branches", "tags"] {
+            let refs_url = format!("{}/repository/{}?per_page={}", project, kind, PER_PAGE);
+            let (refs, _) = self.get_pages::<RefName>(&refs_url, MAX_PAGES).await?;
+            let found = refs.into_iter().map(|r| r.name);
+            match kind {
+                "branches" => names.branches.extend(found),
+                _ => names.tags.extend(found),
+            }
+        }
+        Ok(names)
+    }
+
+    async fn ref_sha(&self, url: &RepoUrl, kind: RefKind, name: &str) -> Result<Option<String>> {
+        let project = project_url(url);
+        let sha = match kind {
+            RefKind::Branch | RefKind::Tag => {
+                let list = if kind == RefKind::Branch { "branches" } else { "tags" };
+                let lookup = format!("{}/repository/{}/{}", project, list, encode(name));
+                found(self.rest.get_json::<RefInfo>(&lookup).await)?.map(|info| info.commit.id)
+            }
+            RefKind::Commit => {
+                let lookup = format!("{}/repository/commits/{}", project, encode(name));
+                found(self.rest.get_json::<CommitInfo>(&lookup).await)?.map(|commit| commit.id)
+            }
+        };
+        Ok(sha)
+    }
+}
+
+#[async_trait]
+impl Provider for GitLabClient {
+    fn set_progress(&self, progress: Progress) {
+        self.rest.set_progress(progress);
+    }
+
+    fn credential_source(&self, host: &Host) -> Option<CredentialSource> {
+        self.rest.credential_source(host)
+    }
+
+    async fn resolve_ref(&self, url: &mut RepoUrl) -> Result<()> {
+        resolve_repo_ref(self, url).await
+    }
+
+    async fn list_dir(&self, url: &RepoUrl) -> Result<Listing> {
+        let project = project_url(url);
+        let reference = url.commitish().unwrap_or("HEAD");
+        let (entries, partial) = self
+            .get_pages::<TreeEntry>(&tree_url(&project, &url.path, reference), MAX_PAGES)
+            .await?;
+        let items = entries
+            .into_iter()
+            .map(|entry| entry.into_item(&project, reference))
+            .collect();
+        Ok(Listing { items, partial })
+    }
+
+    async fn walk_tree(&self, dir: &RepoItem) -> Result<Vec<RepoItem>> {
+        let (project, reference) =
+            split_tree_url(&dir.url).ok_or_else(|| anyhow!("Not a repository folder: {}", dir.url))?;
+        // a recursive listing is one entry per file and folder, so allow far more pages
+        let (entries, truncated) = self
+            .get_pages::<TreeEntry>(&format!("{}&recursive=true", dir.url), MAX_PAGES * 10)
+            .await?;
+        if truncated {
+            return Err(anyhow!("{} has more than {} entries", dir.name, MAX_PAGES * 10 * PER_PAGE));
+        }
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.kind == "blob")
+            .map(|entry| entry.into_item(&project, &reference))
+            .collect())
+    }
+
+    async fn raw_file(&self, url: &str) -> Result<Vec<u8>> {
+        self.rest.download(url).await
+    }
+
+    // Download URLs already ask for `lfs=true`; there are no pointers left to resolve
+    async fn resolve_lfs(&self, _items: &mut [RepoItem], _url: &RepoUrl) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::HostKind;
+
+    #[test]
+    fn test_parse_gitlab_urls() {
+        let parsed = RepoUrl::parse("https://gitlab.com/group/sub/project/-/tree/main/src/lib").unwrap();
+        assert_eq!(parsed.host.kind, HostKind::GitLab);
+        assert_eq!(parsed.owner, "group/sub");
+        assert_eq!(parsed.repo, "project");
+        assert_eq!(parsed.reference.as_deref(), Some("main"));
+        assert_eq!(parsed.path, "src/lib");
+        assert!(parsed.ambiguous_ref);
+
+        let parsed = RepoUrl::parse("https://gitlab.com/group/project/-/blob/v1.0/README.md#L12-20").unwrap();
+        assert!(parsed.is_file());
+        assert_eq!(parsed.lines.map(|l| (l.start, l.end)), Some((12, 20)));
+
+        let parsed = RepoUrl::parse("git@gitlab.com:group/project.git").unwrap();
+        assert_eq!(parsed.repo, "project");
+        assert_eq!(parsed.reference, None);
+
+        let hosts = vec![Host::self_hosted(HostKind::GitLab, "https://git.example.com", None, None)];
+        let parsed = RepoUrl::parse_with_hosts("https://git.example.com/team/tool/-/tree/dev", &hosts).unwrap();
+        assert_eq!(parsed.host.api, "https://git.example.com/api/v4");
+        assert_eq!(project_url(&parsed), "https://git.example.com/api/v4/projects/team%2Ftool");
+
+        assert!(RepoUrl::parse("https://gitlab.com/project").is_err());
+        assert!(RepoUrl::parse("https://gitlab.com/group/project/-/merge_requests/4").is_err());
+    }
+
+    #[test]
+    fn test_tree_urls() {
+        let url = tree_url("https://gitlab.com/api/v4/projects/g%2Fp", "docs/read me", "feature/x");
+        assert_eq!(
+            url,
+            "https://gitlab.com/api/v4/projects/g%2Fp/repository/tree?path=docs%2Fread%20me&ref=feature%2Fx&per_page=100"
+        );
+        let (project, reference) = split_tree_url(&url).unwrap();
+        assert_eq!(project, "https://gitlab.com/api/v4/projects/g%2Fp");
+        assert_eq!(reference, "feature/x");
+
+        let entry = TreeEntry {
+            id: "abc".to_string(),
+            name: "a.txt".to_string(),
+            kind: "blob".to_string(),
+            path: "docs/a.txt".to_string(),
+        };
+        let item = entry.into_item(&project, &reference);
+        assert_eq!(
+            item.download_url.as_deref(),
+            Some("https://gitlab.com/api/v4/projects/g%2Fp/repository/files/docs%2Fa.txt/raw?ref=feature%2Fx&lfs=true")
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{Certificate, NoProxy, Proxy};
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::{self, NetworkConfig};
+
+// Every request in the process goes through this one client, so proxy, extra roots and
+// timeouts only have to be set up once
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+pub fn client() -> Result<reqwest::Client> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client = build(&config::get().network)?;
+    Ok(CLIENT.get_or_init(|| client).clone())
+}
+
+fn build(network: &NetworkConfig) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent("ghgrab/0.1.0")
+        .connect_timeout(Duration::from_secs(network.connect_timeout_secs));
+
+    // without an explicit proxy reqwest follows HTTPS_PROXY / HTTP_PROXY / NO_PROXY itself
+    if let Some(proxy_url) = &network.proxy {
+        let mut proxy = Proxy::all(proxy_url).context(format!("Invalid proxy URL: {}", proxy_url))?;
+        if let Some(username) = &network.proxy_username {
+            proxy = proxy.basic_auth(username, network.proxy_password.as_deref().unwrap_or_default());
+        }
+        let no_proxy = match &network.no_proxy {
+            Some(list) => NoProxy::from_string(list),
+            None => NoProxy::from_env(),
+        };
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+
+    for path in &network.ca_certs {
+        let pem = fs::read(path).context(format!("Failed to read CA bundle: {:?}", path))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .context(format!("Invalid PEM in CA bundle: {:?}", path))?;
+        if certs.is_empty() {
+            return Err(anyhow!("No certificates found in CA bundle: {:?}", path));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    builder.build().context("Failed to create HTTP client")
+}
+
+// reqwest 0.11 only has a total timeout, which would cut off large downloads; this is the
+// longest we wait for response headers or for the next chunk of a body
+pub fn read_timeout() -> Duration {
+    Duration::from_secs(config::get().network.read_timeout_secs)
+}
+
+#[derive(Debug)]
+pub struct ReadTimeout;
+
+impl fmt::Display for ReadTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no data received for {}s", read_timeout().as_secs())
+    }
+}
+
+impl std::error::Error for ReadTimeout {}
+
+// A 404, kept apart from other failures so lookups can treat it as "no such thing"
+#[derive(Debug)]
+pub struct NotFound;
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Path not found in repository")
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<NotFound>())
+}
+
+pub async fn execute(client: &reqwest::Client, request: reqwest::Request) -> Result<reqwest::Response> {
+    match tokio::time::timeout(read_timeout(), client.execute(request)).await {
+        Ok(response) => Ok(response?),
+        Err(_) => Err(ReadTimeout.into()),
+    }
+}
+
+// The whole body, failing if the server goes quiet for longer than the read timeout
+pub async fn read_body(mut response: reqwest::Response) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        match tokio::time::timeout(read_timeout(), response.chunk()).await {
+            Ok(Ok(Some(chunk))) => body.extend_from_slice(&chunk),
+            Ok(Ok(None)) => return Ok(body),
+            Ok(Err(err)) => return Err(err.into()),
+            Err(_) => return Err(ReadTimeout.into()),
+        }
+    }
+}
+
+// Headers and body of a GET sent under the retry policy; None when the server answers
+// 304. A connection dropped mid-body restarts the request on the same schedule.
+pub async fn fetch(api: &impl Api, request: reqwest::RequestBuilder) -> Result<Option<(HeaderMap, Vec<u8>)>> {
+    let retry = &config::get().retry;
+    let mut attempt = 1;
+    loop {
+        let Some(copy) = request.try_clone() else {
+            return Err(anyhow!("A streamed request body can't be fetched again"));
+        };
+        let response = send(api, copy).await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let headers = response.headers().clone();
+        match read_body(response).await {
+            Ok(body) => return Ok(Some((headers, body))),
+            Err(err) if attempt < retry.max_attempts => {
+                let delay = backoff(attempt);
+                attempt += 1;
+                api.report(format!(
+                    "Download interrupted ({}); retrying in {:.1}s (attempt {}/{})",
+                    err,
+                    delay.as_secs_f64(),
+                    attempt,
+                    retry.max_attempts
+                ));
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(err).context("Failed to read file content"),
+        }
+    }
+}
+
+// Worth another try: gateway hiccups, overload, and throttling that says when to come back
+pub fn is_transient_status(status: reqwest::StatusCode, headers: &HeaderMap) -> bool {
+    match status.as_u16() {
+        408 | 429 | 500 | 502 | 503 | 504 => true,
+        403 => headers.contains_key("retry-after"),
+        _ => false,
+    }
+}
+
+pub fn is_transient_error(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_connect() || e.is_timeout() || e.is_request() || e.is_body())
+        || err.chain().any(|cause| cause.is::<ReadTimeout>())
+}
+
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let secs = headers.get("retry-after")?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+// Exponential backoff with jitter: somewhere in the upper half of base * 2^(attempt - 1)
+fn backoff(attempt: u32) -> Duration {
+    let retry = &config::get().retry;
+    let ceiling = retry
+        .base_delay()
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(retry.max_delay());
+    let mut bytes = [0u8; 2];
+    // the OS RNG practically never fails; the midpoint of the range is a fine fallback
+    let jitter = match getrandom::getrandom(&mut bytes) {
+        Ok(()) => u16::from_le_bytes(bytes) % 1000,
+        Err(_) => 500,
+    };
+    ceiling / 2 + ceiling / 2 * jitter as u32 / 1000
+}
+
+// What `send` needs from an API client around its retry loop
+#[async_trait]
+pub trait Api: Sync {
+    // One attempt, with the client's credentials attached
+    async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response>;
+
+    // The final response, or the error it stands for
+    async fn check_status(&self, response: reqwest::Response) -> Result<reqwest::Response>;
+
+    // Where retry notices go
+    fn report(&self, message: String);
+
+    // Sleep through an exhausted quota if the client is set up to; true to replay the request
+    async fn wait_out_quota(&self, _response: &reqwest::Response) -> bool {
+        false
+    }
+}
+
+tokio::task_local! {
+    // Set around calls someone is sitting in front of, which report an exhausted quota
+    // rather than sleep through it
+    static NO_QUOTA_WAIT: ();
+}
+
+// Run `call` without waiting out exhausted quotas, whatever `wait_for_reset` says
+pub async fn without_quota_wait<F: Future>(call: F) -> F::Output {
+    NO_QUOTA_WAIT.scope((), call).await
+}
+
+// Whether a client may sleep until its quota resets
+pub fn may_wait_for_reset() -> bool {
+    config::get().wait_for_reset && NO_QUOTA_WAIT.try_with(|_| ()).is_err()
+}
+
+// Send under the retry policy
+pub async fn send(api: &impl Api, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    let request = request.build().context("Failed to build request")?;
+    let retry = &config::get().retry;
+    // a POST may already have taken effect, so only safe methods are replayed
+    let idempotent = request.method().is_idempotent();
+    let mut attempt = 1;
+
+    loop {
+        // bodies that cannot be cloned (streams) just get a single attempt
+        let Some(copy) = request.try_clone() else {
+            return api.check_status(api.execute(request).await?).await;
+        };
+        let can_retry = idempotent && attempt < retry.max_attempts;
+
+        let (delay, reason) = match api.execute(copy).await {
+            Ok(response) => {
+                if api.wait_out_quota(&response).await {
+                    continue;
+                }
+                let delay = retry_after(response.headers()).unwrap_or_else(|| backoff(attempt));
+                if !can_retry || !is_transient_status(response.status(), response.headers()) || delay > retry.max_delay() {
+                    return api.check_status(response).await;
+                }
+                (delay, response.status().to_string())
+            }
+            Err(err) if can_retry && is_transient_error(&err) => (backoff(attempt), format!("Network error: {}", err.root_cause())),
+            Err(err) => return Err(err),
+        };
+
+        attempt += 1;
+        api.report(format!(
+            "{}; retrying in {:.1}s (attempt {}/{})",
+            reason,
+            delay.as_secs_f64(),
+            attempt,
+            retry.max_attempts
+        ));
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_client() {
+        let network = NetworkConfig {
+            proxy: Some("http://proxy.corp:3128".to_string()),
+            proxy_username: Some("me".to_string()),
+            proxy_password: Some("secret".to_string()),
+            no_proxy: Some("localhost,.corp".to_string()),
+            ..NetworkConfig::default()
+        };
+        assert!(build(&network).is_ok());
+        assert!(!format!("{:?}", network).contains("secret"));
+
+        let missing_ca = NetworkConfig {
+            ca_certs: vec!["/nonexistent/corp-root.pem".into()],
+            ..NetworkConfig::default()
+        };
+        assert!(build(&missing_ca).is_err());
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let none = HeaderMap::new();
+        assert!(is_transient_status(reqwest::StatusCode::BAD_GATEWAY, &none));
+        assert!(!is_transient_status(reqwest::StatusCode::NOT_FOUND, &none));
+        assert!(!is_transient_status(reqwest::StatusCode::FORBIDDEN, &none));
+
+        for attempt in 1..=10 {
+            let ceiling = Duration::from_millis((500u64 << (attempt - 1)).min(30_000));
+            let delay = backoff(attempt);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "attempt {}: {:?}", attempt, delay);
+        }
+    }
+}
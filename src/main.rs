@@ -1,19 +1,50 @@
+// nested `if let`s are kept as written rather than folded into let-chains
+#![allow(clippy::collapsible_if)]
+
+mod auth;
+mod bitbucket;
+mod cache;
+mod cli;
+mod config;
 mod download;
+mod gitea;
 mod github;
+mod gitlab;
+mod http;
+mod provider;
+mod rest;
 mod ui;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::env;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let initial_url = if args.len() > 1 {
-        Some(args[1].clone())
-    } else {
-        None
-    };
-    
-    ui::run_tui(initial_url).await?;
+    let args = cli::Args::parse(env::args().skip(1))?;
+    let mut config = config::Config::load()?;
+    config.wait_for_reset |= args.wait_for_reset;
+    config.cache.enabled &= !args.no_cache;
+    config::init(config);
+
+    if let Some(command) = args.auth {
+        return cli::run_auth(command).await;
+    }
+
+    if args.lines {
+        let url = args
+            .url
+            .ok_or_else(|| anyhow!("A URL is required when using --lines"))?;
+        return cli::run_lines(&url, args.out).await;
+    }
+
+    if let Some(out) = args.out.clone() {
+        let url = args
+            .url
+            .as_deref()
+            .ok_or_else(|| anyhow!("A URL is required when using --out"))?;
+        return cli::run_download(url, out, &args).await;
+    }
+
+    ui::run_tui(args.url).await?;
     Ok(())
 }
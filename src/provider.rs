@@ -0,0 +1,1003 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::auth::CredentialSource;
+use crate::bitbucket;
+use crate::config;
+use crate::github;
+use crate::gitea;
+use crate::gitlab;
+use crate::http;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Dir,
+    File,
+    // Only the files a pull request changed, at its head commit
+    PullRequest(u64),
+    // Gist id lives in `repo`; a revision, if given, in `reference`
+    Gist,
+    Releases,
+    // Assets of the release tagged `reference`, or of the latest release while that is None
+    Release,
+    // Files changed between `base` and `reference`, limited to those under `path`
+    Compare { base: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefKind {
+    Branch,
+    Tag,
+    Commit,
+}
+
+impl RefKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RefKind::Branch => "branch",
+            RefKind::Tag => "tag",
+            RefKind::Commit => "commit",
+        }
+    }
+}
+
+// What a ref turned out to be, and the commit it pointed at when the URL was loaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinnedRef {
+    pub kind: RefKind,
+    pub sha: String,
+}
+
+// Which API a host speaks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostKind {
+    #[default]
+    GitHub,
+    GitLab,
+    // Forgejo is a Gitea fork and keeps its API
+    #[serde(alias = "forgejo")]
+    Gitea,
+    // Bitbucket Cloud only; Server and Data Center speak another API
+    #[serde(skip)]
+    Bitbucket,
+}
+
+impl HostKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HostKind::GitHub => "GitHub",
+            HostKind::GitLab => "GitLab",
+            HostKind::Gitea => "Gitea",
+            HostKind::Bitbucket => "Bitbucket",
+        }
+    }
+
+    // Where the REST API lives on a self-hosted instance
+    fn default_api(&self, web: &str) -> String {
+        match self {
+            HostKind::GitHub => format!("{}/api/v3", web),
+            HostKind::GitLab => format!("{}/api/v4", web),
+            HostKind::Gitea => format!("{}/api/v1", web),
+            HostKind::Bitbucket => "https://api.bitbucket.org/2.0".to_string(),
+        }
+    }
+}
+
+// A hosting instance: github.com, gitlab.com, codeberg.org, bitbucket.org, or a self-hosted server registered in the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct Host {
+    pub kind: HostKind,
+    // Base of the web UI, e.g. https://github.example.com
+    pub web: String,
+    // REST API base, e.g. https://github.example.com/api/v3
+    pub api: String,
+    // Prefix of `<owner>/<repo>.git/info/lfs`
+    pub lfs: String,
+}
+
+impl Host {
+    pub fn github() -> Self {
+        Host {
+            kind: HostKind::GitHub,
+            web: "https://github.com".to_string(),
+            api: "https://api.github.com".to_string(),
+            lfs: "https://github.com".to_string(),
+        }
+    }
+
+    pub fn gitlab() -> Self {
+        Host::self_hosted(HostKind::GitLab, "https://gitlab.com", None, None)
+    }
+
+    pub fn codeberg() -> Self {
+        Host::self_hosted(HostKind::Gitea, "https://codeberg.org", None, None)
+    }
+
+    pub fn bitbucket() -> Self {
+        Host::self_hosted(HostKind::Bitbucket, "https://bitbucket.org", None, None)
+    }
+
+    pub fn self_hosted(kind: HostKind, web: &str, api: Option<&str>, lfs: Option<&str>) -> Self {
+        let web = web.trim_end_matches('/').to_string();
+        Host {
+            kind,
+            api: api
+                .map(|a| a.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| kind.default_api(&web)),
+            lfs: lfs
+                .map(|l| l.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| web.clone()),
+            web,
+        }
+    }
+
+    pub fn is_github_com(&self) -> bool {
+        self.web == "https://github.com"
+    }
+
+    pub fn name(&self) -> &str {
+        self.web.split_once("://").map_or(self.web.as_str(), |(_, rest)| rest)
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let Ok(web) = Url::parse(&self.web) else {
+            return false;
+        };
+        if web.host_str() != url.host_str() {
+            return false;
+        }
+        // clone URLs reach the same instance over another protocol and port
+        is_clone_scheme(url.scheme()) || web.port_or_known_default() == url.port_or_known_default()
+    }
+
+    // Which instance of `public`'s kind a request to `url` goes to: `public` itself over HTTPS,
+    // also under `aliases`, its other host names; otherwise a configured one. Tokens are only
+    // ever sent to their own instance.
+    pub fn for_request(url: &Url, public: &Host, aliases: &[&str]) -> Option<Host> {
+        let on_public = public.serves(url) || url.host_str().is_some_and(|name| aliases.contains(&name));
+        if url.scheme() == "https" && on_public {
+            return Some(public.clone());
+        }
+        config::get()
+            .hosts
+            .iter()
+            .find(|host| host.kind == public.kind && host.serves(url))
+            .cloned()
+    }
+
+    // Whether `url` points at this instance's web, API or LFS server
+    pub fn serves(&self, url: &Url) -> bool {
+        [&self.web, &self.api, &self.lfs].iter().any(|base| {
+            Url::parse(base).is_ok_and(|b| {
+                b.host_str() == url.host_str() && b.port_or_known_default() == url.port_or_known_default()
+            })
+        })
+    }
+}
+
+// Inclusive, 1-based line range from a permalink anchor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    // `L10`, `L10-L42`, the column form `L10C5-L42C8`, GitLab's `L10-42`, and Bitbucket's
+    // `lines-10:42`, of which only the first of several ranges is kept
+    pub fn parse_anchor(fragment: &str) -> Option<Self> {
+        fn line(part: &str) -> Option<usize> {
+            let digits = part.split_once('C').map_or(part, |(l, _)| l);
+            digits.parse().ok().filter(|n| *n > 0)
+        }
+
+        let (start, end) = if let Some(ranges) = fragment.strip_prefix("lines-") {
+            let range = ranges.split(',').next()?;
+            let (start, end) = range.split_once(':').unwrap_or((range, range));
+            (line(start)?, line(end)?)
+        } else {
+            let fragment = fragment.strip_prefix('L')?;
+            match fragment.split_once('-') {
+                Some((start, end)) => (line(start)?, line(end.strip_prefix('L').unwrap_or(end))?),
+                None => {
+                    let n = line(fragment)?;
+                    (n, n)
+                }
+            }
+        };
+        Some(LineRange {
+            start: start.min(end),
+            end: start.max(end),
+        })
+    }
+
+    // `L10-L42`, the anchor most hosts link lines with
+    pub fn anchor(self) -> String {
+        format!("L{}-L{}", self.start, self.end)
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        (self.start..=self.end).contains(&line)
+    }
+
+    pub fn extract(&self, content: &str) -> String {
+        content
+            .lines()
+            .enumerate()
+            .filter(|(idx, _)| self.contains(idx + 1))
+            .map(|(_, line)| format!("{}\n", line))
+            .collect()
+    }
+}
+
+pub fn is_clone_scheme(scheme: &str) -> bool {
+    matches!(scheme, "ssh" | "git" | "git+ssh")
+}
+
+#[derive(Debug, Clone)]
+pub struct RepoUrl {
+    pub host: Host,
+    // GitLab namespaces may contain slashes: `group/subgroup`
+    pub owner: String,
+    pub repo: String,
+    // Branch, tag or commit as written; None until resolved to the default branch
+    pub reference: Option<String>,
+    pub pinned: Option<PinnedRef>,
+    pub path: String,
+    pub target: Target,
+    // The ref/path boundary came from a URL and may fall inside a ref like `feature/x`
+    pub ambiguous_ref: bool,
+    // `#L10-L42` permalink anchor on a file link
+    pub lines: Option<LineRange>,
+}
+
+impl RepoUrl {
+    pub fn parse(url_str: &str) -> Result<Self> {
+        Self::parse_with_hosts(url_str, &config::get().hosts)
+    }
+
+    pub fn parse_with_hosts(url_str: &str, hosts: &[Host]) -> Result<Self> {
+        let url_str = url_str.trim();
+        if !url_str.contains("://") {
+            // scp-style clone URL: git@github.com:owner/repo.git
+            if let Some((user_host, path)) = url_str.split_once(':')
+                && user_host.contains('@')
+                && !user_host.contains('/')
+            {
+                return Self::parse_with_hosts(&format!("ssh://{}/{}", user_host, path), hosts);
+            }
+
+            // `github.com/owner/repo` pasted without a scheme; owners never contain dots
+            let first = url_str.split('/').next().unwrap_or_default();
+            if first.contains('.') {
+                return Self::parse_with_hosts(&format!("https://{}", url_str), hosts);
+            }
+            return github::parse_spec(url_str);
+        }
+
+        let url = Url::parse(url_str).context("Invalid URL format")?;
+        let mut parsed = Self::parse_url(&url, hosts)?;
+        if parsed.is_file() || parsed.host.kind.backend().anchor_on_dirs {
+            parsed.lines = url.fragment().and_then(LineRange::parse_anchor);
+        }
+        Ok(parsed)
+    }
+
+    // Configured instances first, then the public hosts each provider knows
+    fn parse_url(url: &Url, hosts: &[Host]) -> Result<Self> {
+        let path_segments: Vec<&str> = url
+            .path_segments()
+            .ok_or_else(|| anyhow!("Invalid URL path"))?
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        if let Some(host) = hosts.iter().find(|h| h.matches(url)) {
+            return (host.kind.backend().parse_web)(host.clone(), &path_segments);
+        }
+        if let Some(parsed) = BACKENDS.iter().find_map(|b| (b.parse_public)(url, &path_segments)) {
+            return parsed;
+        }
+        if is_clone_scheme(url.scheme()) {
+            return Err(anyhow!("Clone URL host is not a known host"));
+        }
+        Err(anyhow!("Not a GitHub, GitLab, Gitea or Bitbucket URL"))
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.target == Target::File
+    }
+
+    // Folder to list when opening this URL: the file's parent for blob links
+    pub fn dir_url(&self) -> RepoUrl {
+        if !self.is_file() {
+            return self.clone();
+        }
+        let parent = match self.path.rfind('/') {
+            Some(idx) => self.path[..idx].to_string(),
+            None => String::new(),
+        };
+        RepoUrl {
+            path: parent,
+            target: Target::Dir,
+            ..self.clone()
+        }
+    }
+
+    // Where opening `item` from this listing leads, if anywhere
+    pub fn child(&self, item: &RepoItem) -> Option<RepoUrl> {
+        if item.is_release() {
+            return Some(RepoUrl {
+                reference: Some(item.path.clone()),
+                pinned: None,
+                path: String::new(),
+                target: Target::Release,
+                ..self.clone()
+            });
+        }
+        if !item.is_dir() {
+            return None;
+        }
+
+        let path = if self.path.is_empty() {
+            item.name.clone()
+        } else {
+            format!("{}/{}", self.path, item.name)
+        };
+        Some(RepoUrl {
+            path,
+            ..self.clone()
+        })
+    }
+
+    // Web page of the file at the pinned commit
+    pub fn blob_url(&self) -> String {
+        format!(
+            "{}/{}/{}/{}/{}/{}",
+            self.host.web,
+            self.owner,
+            self.repo,
+            self.host.kind.backend().blob,
+            self.commitish().unwrap_or_default(),
+            self.path
+        )
+    }
+
+    // Web page of `range` within the file
+    pub fn lines_url(&self, range: LineRange) -> String {
+        format!("{}#{}", self.blob_url(), (self.host.kind.backend().line_anchor)(range))
+    }
+
+    // Pinned SHA once loaded, so a moving branch can't mix files from two commits
+    pub fn commitish(&self) -> Option<&str> {
+        self.pinned
+            .as_ref()
+            .map(|p| p.sha.as_str())
+            .or(self.reference.as_deref())
+    }
+}
+
+// Split `ref/path` at the longest prefix that names a real ref. Files need a non-empty path.
+pub fn split_ref_path(full: &str, is_ref: impl Fn(&str) -> bool, is_file: bool) -> Option<(String, String)> {
+    let mut cut = full.len();
+    loop {
+        let (candidate, rest) = (&full[..cut], full[cut..].trim_start_matches('/'));
+        if (!is_file || !rest.is_empty()) && is_ref(candidate) {
+            return Some((candidate.to_string(), rest.to_string()));
+        }
+        cut = full[..cut].rfind('/')?;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RefNames {
+    pub branches: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl RefNames {
+    pub fn kind_of(&self, name: &str) -> Option<RefKind> {
+        if self.branches.iter().any(|b| b == name) {
+            Some(RefKind::Branch)
+        } else if self.tags.iter().any(|t| t == name) {
+            Some(RefKind::Tag)
+        } else {
+            None
+        }
+    }
+}
+
+// `<api>/repos/o/r/contents/<path>?ref=<ref>` into the repository's API base and the ref.
+// Matched by segment so owners, repos and hosts named like "contents" still split right.
+pub fn split_contents_url(url: &str) -> Option<(String, Option<String>)> {
+    let mut parsed = Url::parse(url).ok()?;
+    let segments: Vec<&str> = parsed.path_segments()?.collect();
+    let repos = (0..segments.len()).find(|&i| segments[i] == "repos" && segments.get(i + 3) == Some(&"contents"))?;
+    let reference = parsed
+        .query_pairs()
+        .find(|(key, _)| key == "ref")
+        .map(|(_, value)| value.into_owned());
+    let repo_path = segments[..repos + 3].join("/");
+    parsed.set_path(&repo_path);
+    parsed.set_query(None);
+    parsed.set_fragment(None);
+    Some((parsed.to_string(), reference))
+}
+
+pub fn is_full_sha(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct RepoItem {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub path: String,
+    pub download_url: Option<String>,
+    // Provider-specific API URL of the entry; folders are walked from it
+    pub url: String,
+    #[allow(dead_code)]
+    pub size: Option<u64>,
+    // Blob or tree SHA; folders use it to fetch their whole subtree
+    pub sha: Option<String>,
+    #[serde(skip)]
+    pub selected: bool,
+    #[serde(skip)]
+    pub lfs_oid: Option<String>,
+    #[serde(skip)]
+    pub lfs_size: Option<u64>,
+    #[serde(skip)]
+    pub lfs_download_url: Option<String>,
+    // Set when the listing shows changes rather than a folder's contents
+    #[serde(skip)]
+    pub change: Option<ChangeStatus>,
+    // Release assets only
+    #[serde(skip)]
+    pub download_count: Option<u64>,
+    #[serde(skip)]
+    pub content_type: Option<String>,
+}
+
+impl RepoItem {
+    pub fn is_dir(&self) -> bool {
+        self.item_type == "dir"
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.item_type == "file"
+    }
+
+    // Entry of a releases list; opening it shows the release's assets
+    pub fn is_release(&self) -> bool {
+        self.item_type == "release"
+    }
+
+    pub fn is_lfs(&self) -> bool {
+        self.lfs_oid.is_some()
+    }
+
+    pub fn actual_size(&self) -> Option<u64> {
+        self.lfs_size.or(self.size)
+    }
+
+    pub fn actual_download_url(&self) -> Option<&String> {
+        self.lfs_download_url.as_ref().or(self.download_url.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeStatus {
+    Added,
+    Modified,
+    Removed,
+    Renamed { from: String },
+}
+
+impl ChangeStatus {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ChangeStatus::Added => "A",
+            ChangeStatus::Modified => "M",
+            ChangeStatus::Removed => "D",
+            ChangeStatus::Renamed { .. } => "R",
+        }
+    }
+}
+
+// What a view shows, and whether the host may have left entries out of it
+#[derive(Debug, Default)]
+pub struct Listing {
+    pub items: Vec<RepoItem>,
+    pub partial: bool,
+}
+
+impl From<Vec<RepoItem>> for Listing {
+    fn from(items: Vec<RepoItem>) -> Self {
+        Listing { items, partial: false }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LfsPointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+impl LfsPointer {
+    pub fn parse(content: &str) -> Option<Self> {
+        if !content.starts_with("version https://git-lfs.github.com/spec/v1") {
+            return None;
+        }
+
+        let mut oid = None;
+        let mut size = None;
+
+        for line in content.lines() {
+            if line.starts_with("oid sha256:") {
+                oid = Some(line.trim_start_matches("oid sha256:").to_string());
+            } else if line.starts_with("size ") {
+                size = line.trim_start_matches("size ").parse().ok();
+            }
+        }
+
+        match (oid, size) {
+            (Some(oid), Some(size)) => Some(LfsPointer { oid, size }),
+            _ => None,
+        }
+    }
+}
+
+// A client that can look inside files for LFS pointers
+#[async_trait]
+pub trait LfsProbe: http::Api {
+    // The whole body of a small file
+    async fn probe(&self, url: &str) -> Result<Vec<u8>>;
+}
+
+// Fill in the LFS fields of small files that turn out to be pointers. `fallback` names
+// another place to fetch the object when the batch API doesn't answer.
+pub async fn resolve_lfs_pointers(
+    client: &impl LfsProbe,
+    items: &mut [RepoItem],
+    url: &RepoUrl,
+    fallback: impl Fn(&RepoItem) -> Option<String> + Sync,
+) {
+    for item in items.iter_mut() {
+        if item.is_file() {
+            if let Some(size) = item.size {
+                if size < 1024 {
+                    if let Some(download_url) = &item.download_url {
+                        if let Ok(content) = client.probe(download_url).await {
+                            if let Some(pointer) = LfsPointer::parse(&String::from_utf8_lossy(&content)) {
+                                item.lfs_oid = Some(pointer.oid.clone());
+                                item.lfs_size = Some(pointer.size);
+                                item.lfs_download_url = match lfs_download_url(client, url, &pointer).await {
+                                    Ok(href) => Some(href),
+                                    Err(_) => fallback(item),
+                                };
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Where the batch API serves the LFS object behind `pointer`
+async fn lfs_download_url(client: &impl http::Api, url: &RepoUrl, pointer: &LfsPointer) -> Result<String> {
+    let batch_url = format!("{}/{}/{}.git/info/lfs/objects/batch", url.host.lfs, url.owner, url.repo);
+    let request = http::client()?
+        .post(&batch_url)
+        .header("Accept", "application/vnd.git-lfs+json")
+        .header("Content-Type", "application/vnd.git-lfs+json")
+        .json(&LfsBatchRequest::download(&pointer.oid, pointer.size));
+    let body = http::read_body(http::send(client, request).await?).await?;
+    let batch: LfsBatchResponse = serde_json::from_slice(&body).context("Failed to parse LFS response")?;
+    batch
+        .download_href()
+        .ok_or_else(|| anyhow!("No download URL in LFS response"))
+}
+
+// Body of a Git LFS batch API request
+#[derive(Debug, serde::Serialize)]
+struct LfsBatchRequest {
+    operation: String,
+    transfers: Vec<String>,
+    objects: Vec<LfsObject>,
+}
+
+impl LfsBatchRequest {
+    pub fn download(oid: &str, size: u64) -> Self {
+        LfsBatchRequest {
+            operation: "download".to_string(),
+            transfers: vec!["basic".to_string()],
+            objects: vec![LfsObject {
+                oid: oid.to_string(),
+                size,
+            }],
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct LfsObject {
+    oid: String,
+    size: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LfsBatchResponse {
+    objects: Vec<LfsResponseObject>,
+}
+
+impl LfsBatchResponse {
+    // Where the first object can be downloaded from
+    pub fn download_href(self) -> Option<String> {
+        self.objects
+            .into_iter()
+            .next()
+            .and_then(|obj| obj.actions)
+            .and_then(|actions| actions.download)
+            .map(|download| download.href)
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LfsResponseObject {
+    #[allow(dead_code)]
+    oid: String,
+    #[allow(dead_code)]
+    size: u64,
+    actions: Option<LfsActions>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LfsActions {
+    download: Option<LfsDownloadAction>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LfsDownloadAction {
+    href: String,
+}
+
+// Sink for human-readable status lines during long operations
+pub type Progress = Arc<dyn Fn(String) + Send + Sync>;
+
+// An API quota as a host last reported it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    pub used: u64,
+    // Unix time the window resets
+    pub reset: u64,
+}
+
+impl RateLimit {
+    pub fn resets_in(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Duration::from_secs(self.reset.saturating_sub(now))
+    }
+}
+
+// What the browser and the downloader need from a hosting service. URL parsing is not part
+// of it: `RepoUrl::parse` picks the provider from the host before any client exists.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    // Where waits and retries are reported
+    fn set_progress(&self, progress: Progress);
+
+    // Where requests to `host` get their token from, without minting one
+    fn credential_source(&self, host: &Host) -> Option<CredentialSource>;
+
+    // Most recent quota seen on an API response from `host`, for hosts that report one
+    fn rate_limit(&self, host: &Host) -> Option<RateLimit> {
+        let _ = host;
+        None
+    }
+
+    // Fill in what the URL left open: the default branch, where a slashed ref like
+    // `feature/x` ends, and the commit the ref points at right now
+    async fn resolve_ref(&self, url: &mut RepoUrl) -> Result<()>;
+
+    // Everything the URL's view shows
+    async fn list_dir(&self, url: &RepoUrl) -> Result<Listing>;
+
+    // Every file below a folder item of a listing, with full paths
+    async fn walk_tree(&self, dir: &RepoItem) -> Result<Vec<RepoItem>>;
+
+    // A whole file from its download URL
+    async fn raw_file(&self, url: &str) -> Result<Vec<u8>>;
+
+    // Point Git LFS pointers among `items` at the objects they stand for
+    async fn resolve_lfs(&self, items: &mut [RepoItem], url: &RepoUrl);
+
+    // Unified diff of a comparison or pull request
+    async fn fetch_diff(&self, url: &RepoUrl) -> Result<String> {
+        let _ = url;
+        Err(anyhow!("Only comparisons and pull requests have a patch"))
+    }
+}
+
+// Branch and tag names per repository, listed at most once a session
+#[derive(Default)]
+pub struct RefCache(Mutex<HashMap<String, RefNames>>);
+
+// The host-specific steps of `resolve_repo_ref`
+#[async_trait]
+pub trait RefLookup: Sync {
+    fn ref_cache(&self) -> &RefCache;
+
+    // The branch a link without a ref shows
+    async fn default_branch(&self, url: &RepoUrl) -> Result<String>;
+
+    // Every branch and tag in the repository
+    async fn fetch_ref_names(&self, url: &RepoUrl) -> Result<RefNames>;
+
+    // The commit behind the branch, tag or commit called `name`; None if there is none
+    async fn ref_sha(&self, url: &RepoUrl, kind: RefKind, name: &str) -> Result<Option<String>>;
+
+    // Whether the folder path of a pinned link is really a file, for hosts whose links
+    // don't tell the two apart
+    async fn is_file(&self, url: &RepoUrl) -> Result<bool> {
+        let _ = url;
+        Ok(false)
+    }
+}
+
+// A 404 from a lookup means "no such ref"; any other failure is still an error
+pub fn found<T>(lookup: Result<T>) -> Result<Option<T>> {
+    match lookup {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if http::is_not_found(&err) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+async fn ref_names(lookup: &impl RefLookup, url: &RepoUrl) -> Result<RefNames> {
+    let key = format!("{}/{}/{}", url.host.name(), url.owner, url.repo);
+    if let Some(names) = lookup.ref_cache().0.lock().unwrap().get(&key) {
+        return Ok(names.clone());
+    }
+    let names = lookup.fetch_ref_names(url).await?;
+    lookup.ref_cache().0.lock().unwrap().insert(key, names.clone());
+    Ok(names)
+}
+
+// Fill in the default branch, where a slashed ref like `feature/x` ends, and the commit
+// the ref points at right now; what `Provider::resolve_ref` does for repository contents
+pub async fn resolve_repo_ref(lookup: &impl RefLookup, url: &mut RepoUrl) -> Result<()> {
+    let mut kind = None;
+    if url.reference.is_none() {
+        url.reference = Some(lookup.default_branch(url).await?);
+        kind = Some(RefKind::Branch);
+    }
+
+    // `feature/x/src` splits at the longest prefix naming a branch or tag; when the lists
+    // can't be fetched, the first segment is tried as the ref on its own
+    if url.ambiguous_ref {
+        if let Ok(names) = ref_names(lookup, url).await {
+            let full = format!("{}/{}", url.reference.as_deref().unwrap_or_default(), url.path);
+            if let Some((reference, path)) = split_ref_path(&full, |r| names.kind_of(r).is_some(), url.is_file()) {
+                kind = names.kind_of(&reference);
+                url.reference = Some(reference);
+                url.path = path;
+            }
+        }
+        url.ambiguous_ref = false;
+    }
+
+    let reference = url.reference.clone().unwrap_or_default();
+    let pinned = pin_ref(lookup, url, &reference, kind).await?;
+    url.pinned = Some(pinned.ok_or_else(|| anyhow!("Ref not found: {}", reference))?);
+
+    if url.target == Target::Dir && !url.path.is_empty() && lookup.is_file(url).await? {
+        url.target = Target::File;
+    }
+    // a line anchor on a folder link means nothing
+    if !url.is_file() {
+        url.lines = None;
+    }
+    Ok(())
+}
+
+// What `reference` names and its commit, asking for a branch, then a tag, then a commit;
+// `kind` skips the guessing when it is already known
+async fn pin_ref(
+    lookup: &impl RefLookup,
+    url: &RepoUrl,
+    reference: &str,
+    kind: Option<RefKind>,
+) -> Result<Option<PinnedRef>> {
+    let kinds = match kind {
+        Some(kind) => vec![kind],
+        None if is_full_sha(reference) => vec![RefKind::Commit],
+        None => vec![RefKind::Branch, RefKind::Tag, RefKind::Commit],
+    };
+    for kind in kinds {
+        if let Some(sha) = lookup.ref_sha(url, kind, reference).await? {
+            return Ok(Some(PinnedRef { kind, sha }));
+        }
+    }
+    Ok(None)
+}
+
+// What the rest of the crate needs from one kind of host; each backend module defines its own
+pub struct Backend {
+    pub kind: HostKind,
+    pub new_client: fn() -> Result<Box<dyn Provider>>,
+    // A web link on an instance of this kind
+    pub parse_web: fn(Host, &[&str]) -> Result<RepoUrl>,
+    // A link to a public instance known without any config; None when it is on none of them
+    pub parse_public: fn(&Url, &[&str]) -> Option<Result<RepoUrl>>,
+    // What goes between `<owner>/<repo>` and `<sha>/<path>` on a file's web page
+    pub blob: &'static str,
+    pub line_anchor: fn(LineRange) -> String,
+    // Links that only turn out to be files once resolved keep their line anchor for now
+    pub anchor_on_dirs: bool,
+}
+
+pub const BACKENDS: [&Backend; 4] = [&github::BACKEND, &gitlab::BACKEND, &gitea::BACKEND, &bitbucket::BACKEND];
+
+impl HostKind {
+    pub fn backend(&self) -> &'static Backend {
+        BACKENDS
+            .iter()
+            .find(|b| b.kind == *self)
+            .expect("every host kind has a backend")
+    }
+}
+
+// One client per kind of host, shared by every URL opened in a session
+pub struct Providers {
+    clients: Vec<(HostKind, Box<dyn Provider>)>,
+}
+
+impl Providers {
+    pub fn new() -> Result<Self> {
+        let clients = BACKENDS
+            .iter()
+            .map(|b| Ok((b.kind, (b.new_client)()?)))
+            .collect::<Result<_>>()?;
+        Ok(Providers { clients })
+    }
+
+    pub fn for_host(&self, host: &Host) -> &dyn Provider {
+        self.clients
+            .iter()
+            .find(|(kind, _)| *kind == host.kind)
+            .map(|(_, client)| client.as_ref())
+            .expect("every host kind has a client")
+    }
+
+    pub fn set_progress(&self, progress: Progress) {
+        for (_, client) in &self.clients {
+            client.set_progress(progress.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tag `release` and branch `release/1.0`, each pointing at its own commit
+    struct Refs(RefCache);
+
+    #[async_trait]
+    impl RefLookup for Refs {
+        fn ref_cache(&self) -> &RefCache {
+            &self.0
+        }
+
+        async fn default_branch(&self, _url: &RepoUrl) -> Result<String> {
+            Ok("main".to_string())
+        }
+
+        async fn fetch_ref_names(&self, _url: &RepoUrl) -> Result<RefNames> {
+            Ok(RefNames {
+                branches: vec!["main".to_string(), "release/1.0".to_string()],
+                tags: vec!["release".to_string()],
+            })
+        }
+
+        async fn ref_sha(&self, _url: &RepoUrl, kind: RefKind, name: &str) -> Result<Option<String>> {
+            Ok(match (kind, name) {
+                (RefKind::Branch, "release/1.0") => Some("b".repeat(40)),
+                (RefKind::Tag, "release") => Some("t".repeat(40)),
+                _ => None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_longest_ref() {
+        let refs = Refs(RefCache::default());
+        let hosts = vec![Host::self_hosted(HostKind::GitHub, "https://ghe.example.com", None, None)];
+        let mut url = RepoUrl::parse_with_hosts("https://ghe.example.com/o/r/tree/release/1.0/src", &hosts).unwrap();
+        resolve_repo_ref(&refs, &mut url).await.unwrap();
+        assert_eq!(url.reference.as_deref(), Some("release/1.0"));
+        assert_eq!(url.path, "src");
+        assert_eq!(url.pinned.map(|p| (p.kind, p.sha)), Some((RefKind::Branch, "b".repeat(40))));
+
+        let mut url = RepoUrl::parse_with_hosts("https://ghe.example.com/o/r/tree/release/2.0/src", &hosts).unwrap();
+        resolve_repo_ref(&refs, &mut url).await.unwrap();
+        assert_eq!(url.reference.as_deref(), Some("release"));
+        assert_eq!(url.path, "2.0/src");
+        assert_eq!(url.pinned.map(|p| p.kind), Some(RefKind::Tag));
+    }
+
+    #[test]
+    fn test_split_ref_path() {
+        let refs = ["main", "feature", "feature/login-flow"];
+        let is_ref = |r: &str| refs.contains(&r);
+
+        let split = split_ref_path("feature/login-flow/src", is_ref, false).unwrap();
+        assert_eq!(split, ("feature/login-flow".to_string(), "src".to_string()));
+
+        let split = split_ref_path("feature/login-flow", is_ref, false).unwrap();
+        assert_eq!(split, ("feature/login-flow".to_string(), String::new()));
+
+        // a file link can't consume its own file name as part of the ref
+        let split = split_ref_path("feature/login-flow", is_ref, true).unwrap();
+        assert_eq!(split, ("feature".to_string(), "login-flow".to_string()));
+
+        assert!(split_ref_path("release/1.0/docs", is_ref, false).is_none());
+    }
+
+    #[test]
+    fn test_split_contents_url() {
+        let (repo_api, reference) =
+            split_contents_url("https://api.github.com/repos/owner/repo/contents/docs/api?ref=abc123").unwrap();
+        assert_eq!(repo_api, "https://api.github.com/repos/owner/repo");
+        assert_eq!(reference.as_deref(), Some("abc123"));
+
+        let (_, reference) = split_contents_url("https://api.github.com/repos/owner/repo/contents/docs").unwrap();
+        assert_eq!(reference, None);
+
+        let (split, _) =
+            split_contents_url("https://contents.corp/api/v3/repos/owner/contentstack/contents/contents?ref=main").unwrap();
+        assert_eq!(split, "https://contents.corp/api/v3/repos/owner/contentstack");
+        assert!(split_contents_url("https://api.github.com/repos/owner/repo").is_none());
+    }
+
+    #[test]
+    fn test_parse_line_anchor() {
+        assert_eq!(LineRange::parse_anchor("L10-L42"), Some(LineRange { start: 10, end: 42 }));
+        assert_eq!(LineRange::parse_anchor("L7"), Some(LineRange { start: 7, end: 7 }));
+        assert_eq!(LineRange::parse_anchor("L3C2-L5C9"), Some(LineRange { start: 3, end: 5 }));
+        assert_eq!(LineRange::parse_anchor("L12-20"), Some(LineRange { start: 12, end: 20 }));
+        assert_eq!(LineRange::parse_anchor("lines-4:9,20"), Some(LineRange { start: 4, end: 9 }));
+        assert_eq!(LineRange::parse_anchor("readme"), None);
+
+        let range = LineRange { start: 2, end: 3 };
+        assert_eq!(range.extract("one\ntwo\nthree\nfour\n"), "two\nthree\n");
+    }
+
+    #[test]
+    fn test_lines_url() {
+        let mut url = RepoUrl::parse("https://gitlab.com/group/project/-/blob/main/src/lib.rs").unwrap();
+        url.pinned = Some(PinnedRef {
+            kind: RefKind::Branch,
+            sha: "abc123".to_string(),
+        });
+        let range = LineRange { start: 3, end: 5 };
+        assert_eq!(
+            url.lines_url(range),
+            "https://gitlab.com/group/project/-/blob/abc123/src/lib.rs#L3-L5"
+        );
+
+        url.host = Host::bitbucket();
+        assert_eq!(url.lines_url(range), "https://bitbucket.org/group/project/src/abc123/src/lib.rs#lines-3:5");
+    }
+}
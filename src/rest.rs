@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use reqwest::header::HeaderName;
+use serde::de::DeserializeOwned;
+use std::sync::Mutex;
+use url::Url;
+
+use crate::auth::{self, Credential, CredentialCache, CredentialSource};
+use crate::github::format_wait;
+use crate::http::{self, Api};
+use crate::provider::{Host, HostKind, LfsProbe, Progress};
+
+// Plumbing shared by the providers that authenticate with a plain token header:
+// per-instance credentials, the retry policy and readable errors
+pub struct RestClient {
+    client: reqwest::Client,
+    // Public instance of this kind, reachable without any config
+    public: Host,
+    // Header carrying a credential's token
+    auth: fn(&str) -> (HeaderName, String),
+    credentials: CredentialCache,
+    progress: Mutex<Option<Progress>>,
+}
+
+impl RestClient {
+    pub fn new(public: Host, auth: fn(&str) -> (HeaderName, String)) -> Result<Self> {
+        Ok(RestClient {
+            client: http::client()?,
+            public,
+            auth,
+            credentials: CredentialCache::default(),
+            progress: Mutex::new(None),
+        })
+    }
+
+    pub fn kind(&self) -> HostKind {
+        self.public.kind
+    }
+
+    pub fn set_progress(&self, progress: Progress) {
+        *self.progress.lock().unwrap() = Some(progress);
+    }
+
+    pub fn report(&self, message: String) {
+        if let Some(callback) = self.progress.lock().unwrap().as_ref() {
+            callback(message);
+        }
+    }
+
+    fn host_for(&self, url: &Url) -> Option<Host> {
+        Host::for_request(url, &self.public, &[])
+    }
+
+    pub fn credential(&self, host: &Host) -> Option<Credential> {
+        self.credentials.get(host)
+    }
+
+    pub fn credential_source(&self, host: &Host) -> Option<CredentialSource> {
+        self.credential(host).map(|c| c.source)
+    }
+
+    pub fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let body = http::read_body(self.send(self.get(url)).await?).await?;
+        serde_json::from_slice(&body).context(format!("Failed to parse {} API response", self.kind().label()))
+    }
+
+    // A whole file body, fetched again if the connection drops mid-body
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        Ok(http::fetch(self, self.get(url)).await?.map(|(_, body)| body).unwrap_or_default())
+    }
+
+    // Send under the shared retry policy
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        http::send(self, request).await
+    }
+}
+
+#[async_trait]
+impl Api for RestClient {
+    async fn check_status(&self, response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let host = self.host_for(response.url());
+        let wait = http::retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        let message = error_message(&body);
+        match status.as_u16() {
+            // private repositories look like missing ones to anonymous requests
+            404 => Err(http::NotFound.into()),
+            401 => Err(anyhow!("Authentication required: {}", token_hint(host.as_ref()))),
+            429 => match wait {
+                Some(wait) => Err(anyhow!("Rate limit exceeded; retry in {}", format_wait(wait))),
+                None => Err(anyhow!("Rate limit exceeded")),
+            },
+            403 if !message.is_empty() => Err(anyhow!("Access denied: {}", message)),
+            403 => Err(anyhow!("Access denied: your credentials lack permission for this resource")),
+            _ => Err(anyhow!("{} API error: {}", self.kind().label(), status)),
+        }
+    }
+
+    // Attach the instance's token and send; only a rejected token is treated as an error here
+    async fn execute(&self, mut request: reqwest::Request) -> Result<reqwest::Response> {
+        let credential = self.host_for(request.url()).and_then(|host| self.credential(&host));
+        if let Some(credential) = &credential {
+            let (name, value) = (self.auth)(&credential.token);
+            auth::authorize(&mut request, name, &value)?;
+        }
+
+        let response = http::execute(&self.client, request)
+            .await
+            .context(format!("Failed to send request to {} API", self.kind().label()))?;
+        auth::check_rejected(response, credential.as_ref())
+    }
+
+    fn report(&self, message: String) {
+        RestClient::report(self, message)
+    }
+}
+
+#[async_trait]
+impl LfsProbe for RestClient {
+    async fn probe(&self, url: &str) -> Result<Vec<u8>> {
+        self.download(url).await
+    }
+}
+
+// Percent-encode all but unreserved characters, for query values and single path segments.
+// GitLab also takes `group/project` as the one segment `group%2Fproject`.
+pub fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Like `encode`, but keeping the slashes between segments
+pub fn encode_path(path: &str) -> String {
+    path.split('/').map(encode).collect::<Vec<_>>().join("/")
+}
+
+// Where a token for `host` would be picked up from
+fn token_hint(host: Option<&Host>) -> String {
+    let Some(host) = host else {
+        return "this repository needs a token".to_string();
+    };
+    if host.kind == HostKind::Bitbucket {
+        return "set BITBUCKET_USERNAME and BITBUCKET_APP_PASSWORD to an app password".to_string();
+    }
+    match auth::env_vars(host.kind, host.name()) {
+        [] => format!("add a token for {} to ~/.netrc", host.name()),
+        vars => format!("set {} to an access token", vars.join(" or ")),
+    }
+}
+
+// `message` of an error body, which some APIs nest or call `error`
+fn error_message(body: &str) -> String {
+    let value: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    match value.get("message").or_else(|| value.get("error")) {
+        Some(serde_json::Value::String(message)) => message.clone(),
+        Some(serde_json::Value::Object(inner)) => inner
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        Some(other) => other.to_string(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(error_message(r#"{"message":"403 Forbidden"}"#), "403 Forbidden");
+        assert_eq!(error_message(r#"{"type":"error","error":{"message":"Access denied"}}"#), "Access denied");
+        assert_eq!(error_message("<html>"), "");
+    }
+}
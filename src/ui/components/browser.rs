@@ -6,17 +6,23 @@ use ratatui::{
     Frame,
 };
 
-use crate::github::{GitHubUrl, RepoItem};
+use crate::download::format_size;
+use crate::github::format_wait;
+use crate::provider::RateLimit;
+use crate::provider::{ChangeStatus, RefKind, RepoItem, RepoUrl, Target};
 use crate::ui::theme::*;
 
 pub struct BrowserState<'a> {
     pub items: &'a [RepoItem],
-    pub current_url: Option<&'a GitHubUrl>,
+    pub current_url: Option<&'a RepoUrl>,
     pub cursor: usize,
     pub scroll_offset: usize,
     #[allow(dead_code)]
     pub status_msg: &'a str,
     pub is_downloading: bool,
+    pub auth_source: Option<&'a str>,
+    pub rate_limit: Option<RateLimit>,
+    pub partial: bool,
 }
 
 pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
@@ -43,34 +49,57 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
     };
 
     let breadcrumb_text = if let Some(url) = state.current_url {
-        format!(" {}/{} : {}", url.owner, url.repo, url.path)
+        let reference = url.reference.as_deref().unwrap_or("default");
+        let ref_label = match &url.pinned {
+            Some(pinned) if pinned.kind == RefKind::Commit => {
+                format!("commit {}", &pinned.sha[..pinned.sha.len().min(7)])
+            }
+            Some(pinned) => format!(
+                "{} {} ({})",
+                pinned.kind.label(),
+                reference,
+                &pinned.sha[..pinned.sha.len().min(7)]
+            ),
+            None => reference.to_string(),
+        };
+        let host = if url.host.is_github_com() {
+            String::new()
+        } else {
+            format!("{}/", url.host.name())
+        };
+        match url.target {
+            Target::Gist => format!(" gist {}/{} @ {}", url.owner, url.repo, ref_label),
+            Target::Compare { ref base } => format!(
+                " {}{}/{} : {}...{} : {}",
+                host, url.owner, url.repo, base, ref_label, url.path
+            ),
+            Target::Releases => format!(" {}{}/{} : releases", host, url.owner, url.repo),
+            Target::Release => format!(" {}{}/{} : release {}", host, url.owner, url.repo, ref_label),
+            Target::PullRequest(number) => {
+                format!(" {}{}/{} : PR #{} @ {}", host, url.owner, url.repo, number, ref_label)
+            }
+            _ => format!(" {}{}/{} @ {} : {}", host, url.owner, url.repo, ref_label, url.path),
+        }
     } else {
         " Loading...".to_string()
     };
 
+    let title = match state.auth_source {
+        Some(source) => format!(" Repository · auth: {} ", source),
+        None => " Repository · anonymous ".to_string(),
+    };
+
     let header = Paragraph::new(breadcrumb_text)
         .block(
             Block::default()
                 .borders(Borders::ALL)  
-                .title(" Repository ")
+                .title(title)
                 .border_style(Style::default().fg(ACCENT_COLOR))
                 .style(Style::default().bg(BG_COLOR)),
         )
         .style(Style::default().fg(FG_COLOR).add_modifier(Modifier::BOLD));
     f.render_widget(header, chunks[0]);
 
-    fn format_size(size: u64) -> String {
-        if size < 1024 {
-            format!("{} B", size)
-        } else if size < 1024 * 1024 {
-            format!("{:.1} KB", size as f64 / 1024.0)
-        } else if size < 1024 * 1024 * 1024 {
-            format!("{:.1} MB", size as f64 / (1024.0 * 1024.0))
-        } else {
-            format!("{:.1} GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
-        }
-    }
-
     fn get_file_type(name: &str, is_dir: bool) -> String {
         if is_dir {
             "DIR".to_string()
@@ -83,6 +112,14 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
         }
     }
 
+    // Download count and content type, shown for release assets
+    fn asset_details(item: &RepoItem) -> String {
+        match (item.download_count, &item.content_type) {
+            (Some(count), Some(content_type)) => format!("  {:>8} ⬇  {}", count, content_type),
+            _ => String::new(),
+        }
+    }
+
     let header_line = Line::from(vec![
         Span::styled("    ", Style::default().bg(BORDER_COLOR)),
         Span::styled(format!("{:<41}", "Name"), Style::default().fg(FG_COLOR).add_modifier(Modifier::BOLD).bg(BORDER_COLOR)),
@@ -103,7 +140,13 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
         .map(|(idx, item)| {
             let is_selected = idx == state.cursor;
             
-            let icon = if item.is_dir() { "📁 " } else { "📄 " };
+            let icon = if item.is_dir() {
+                "📁 "
+            } else if item.is_release() {
+                "🏷 "
+            } else {
+                "📄 "
+            };
 
             let mark = if item.selected { 
                 Span::styled("[●] ", Style::default().fg(SUCCESS_COLOR))
@@ -113,15 +156,19 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
             
             let name_style = if is_selected {
                 Style::default().fg(ACCENT_COLOR).add_modifier(Modifier::BOLD).bg(HIGHLIGHT_BG)
-            } else if item.is_dir() {
+            } else if item.is_dir() || item.is_release() {
                 Style::default().fg(FOLDER_COLOR)
             } else {
                 Style::default().fg(FG_COLOR)
             };
 
-            let file_type = get_file_type(&item.name, item.is_dir());
+            let file_type = if item.is_release() {
+                "RELEASE".to_string()
+            } else {
+                get_file_type(&item.name, item.is_dir())
+            };
             
-            let size_display = if !item.is_dir() {
+            let size_display = if !item.is_dir() && !item.is_release() {
                 item.actual_size()
                     .map(|s| format!("{:>12}", format_size(s)))
                     .unwrap_or_else(|| format!("{:>12}", "-"))
@@ -145,16 +192,32 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
                 item.name.clone()
             };
 
-            let name_with_icon = format!("{}{}", icon, display_name);
-            let name_display = format!("{:<40}", name_with_icon);
+            // changed-file listings swap the icon for a status letter
+            let (icon_span, name_display) = match &item.change {
+                Some(change) => {
+                    let color = match change {
+                        ChangeStatus::Added => SUCCESS_COLOR,
+                        ChangeStatus::Modified => WARNING_COLOR,
+                        ChangeStatus::Removed => ERROR_COLOR,
+                        ChangeStatus::Renamed { .. } => ACCENT_COLOR,
+                    };
+                    (
+                        Span::styled(format!("{}  ", change.symbol()), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+                        format!("{:<38}", display_name),
+                    )
+                }
+                None => (Span::raw(""), format!("{:<40}", format!("{}{}", icon, display_name))),
+            };
             
             let content = Line::from(vec![
                 mark,
-                Span::styled(name_display, name_style.clone()),
+                icon_span,
+                Span::styled(name_display, name_style),
                 Span::styled("  ", Style::default()),
                 Span::styled(format!("{:<8}", file_type), Style::default().fg(WARNING_COLOR)),
                 Span::styled("  ", Style::default()),
                 Span::styled(size_display, Style::default().fg(BORDER_COLOR)),
+                Span::styled(asset_details(item), Style::default().fg(BORDER_COLOR)),
             ]);
             
             let item = ListItem::new(content);
@@ -171,8 +234,12 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
     let list = List::new(all_items).block(
         Block::default()
             .borders(Borders::ALL)
-            .title(format!(" Files ({}) ", state.items.len()))
-            .border_style(Style::default().fg(if state.is_downloading { WARNING_COLOR } else { BORDER_COLOR }))
+            .title(if state.partial {
+                format!(" Files ({}) · partial view, the host did not list everything ", state.items.len())
+            } else {
+                format!(" Files ({}) ", state.items.len())
+            })
+            .border_style(Style::default().fg(if state.is_downloading || state.partial { WARNING_COLOR } else { BORDER_COLOR }))
             .style(Style::default().bg(BG_COLOR)),
     );
     f.render_widget(list, chunks[1]);
@@ -193,6 +260,21 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
         ]))
         .style(Style::default().bg(BG_COLOR));
         f.render_widget(status, chunks[2]);
+    } else if let Some(limit) = state.rate_limit {
+        // remaining API quota in the spacer line, turning red when it runs low
+        let color = if limit.remaining * 10 < limit.limit { ERROR_COLOR } else { BORDER_COLOR };
+        let quota = Paragraph::new(Span::styled(
+            format!(
+                "API quota {}/{} · resets in {} ",
+                limit.remaining,
+                limit.limit,
+                format_wait(limit.resets_in())
+            ),
+            Style::default().fg(color),
+        ))
+        .alignment(ratatui::layout::Alignment::Right)
+        .style(Style::default().bg(BG_COLOR));
+        f.render_widget(quota, chunks[2]);
     }
 
     let help_spans = vec![
@@ -203,6 +285,9 @@ pub fn render(f: &mut Frame, area: Rect, state: &BrowserState) {
         Span::styled("Enter", Style::default().fg(SUCCESS_COLOR).add_modifier(Modifier::BOLD)),
         Span::styled(" Open", Style::default().fg(BORDER_COLOR)),
         Span::styled("  │  ", Style::default().fg(BORDER_COLOR)),
+        Span::styled("p", Style::default().fg(ACCENT_COLOR).add_modifier(Modifier::BOLD)),
+        Span::styled(" Preview", Style::default().fg(BORDER_COLOR)),
+        Span::styled("  │  ", Style::default().fg(BORDER_COLOR)),
         Span::styled("Space", Style::default().fg(WARNING_COLOR).add_modifier(Modifier::BOLD)),
         Span::styled(" Select", Style::default().fg(BORDER_COLOR)),
         Span::styled("  │  ", Style::default().fg(BORDER_COLOR)),
@@ -18,7 +18,7 @@ pub fn render(f: &mut Frame, area: Rect, input_text: &str, status_msg: &str, cur
             Constraint::Length(1),       // Spacing
             Constraint::Length(3),       // Input box
             Constraint::Length(1),       // Spacing
-            Constraint::Length(9),       // Instructions & Examples
+            Constraint::Length(10),      // Instructions & Examples
             Constraint::Length(2),       // Controls
             Constraint::Min(0),          // Bottom padding
         ])
@@ -68,7 +68,7 @@ pub fn render(f: &mut Frame, area: Rect, input_text: &str, status_msg: &str, cur
 
 
     let desc_text = Line::from(Span::styled(
-        "Download any file or folder from GitHub. No full clones. Just what you need.",
+        "Download any file or folder from GitHub, GitLab, Gitea or Bitbucket. No full clones. Just what you need.",
         Style::default().fg(FG_COLOR).add_modifier(Modifier::ITALIC),
     ));
     let desc = Paragraph::new(desc_text)
@@ -97,7 +97,7 @@ pub fn render(f: &mut Frame, area: Rect, input_text: &str, status_msg: &str, cur
             Block::default()
                 .borders(Borders::ALL)
                 .title(Span::styled(
-                    " Enter GitHub URL ",
+                    " Enter a repository URL or owner/repo ",
                     Style::default().fg(ACCENT_COLOR).add_modifier(Modifier::BOLD)
                 ))
                 .border_style(Style::default().fg(ACCENT_COLOR))
@@ -133,10 +133,14 @@ pub fn render(f: &mut Frame, area: Rect, input_text: &str, status_msg: &str, cur
             Span::styled("  3. ", Style::default().fg(BORDER_COLOR)),
             Span::styled("https://github.com/user/repo/tree/main/specific-folder", Style::default().fg(ACCENT_COLOR)),
         ]),
+        Line::from(vec![
+            Span::styled("  4. ", Style::default().fg(BORDER_COLOR)),
+            Span::styled("user/repo@v1.2.0:docs/api", Style::default().fg(ACCENT_COLOR)),
+        ]),
         Line::from(""),
         Line::from(vec![
             Span::styled("Tip: ", Style::default().fg(WARNING_COLOR).add_modifier(Modifier::BOLD)),
-            Span::styled("Works with any public GitHub repository or folder path", Style::default().fg(FG_COLOR).add_modifier(Modifier::ITALIC)),
+            Span::styled("Works with any public GitHub, GitLab, Gitea or Bitbucket repository or folder path", Style::default().fg(FG_COLOR).add_modifier(Modifier::ITALIC)),
         ]),
     ];
         
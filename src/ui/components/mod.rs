@@ -1,3 +1,4 @@
 pub mod input;
 pub mod browser;
+pub mod preview;
 pub mod toast;
@@ -0,0 +1,100 @@
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use crate::provider::LineRange;
+use crate::ui::theme::*;
+
+pub struct Preview {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub highlight: Option<LineRange>,
+    pub scroll: usize,
+}
+
+impl Preview {
+    pub fn new(title: String, content: &str, highlight: Option<LineRange>) -> Self {
+        // open with a little context above the highlighted range
+        let scroll = highlight.map_or(0, |r| r.start.saturating_sub(4));
+        Self {
+            title,
+            lines: content.lines().map(|l| l.replace('\t', "    ")).collect(),
+            highlight,
+            scroll,
+        }
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        let max = self.lines.len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+}
+
+pub fn render(f: &mut Frame, area: Rect, preview: &Preview) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(5),     // File content
+            Constraint::Length(2),  // Help
+        ])
+        .split(area);
+
+    let gutter = preview.lines.len().to_string().len();
+    let visible = chunks[0].height.saturating_sub(2) as usize;
+
+    let lines: Vec<Line> = preview
+        .lines
+        .iter()
+        .enumerate()
+        .skip(preview.scroll)
+        .take(visible)
+        .map(|(idx, text)| {
+            let line_no = idx + 1;
+            let highlighted = preview.highlight.is_some_and(|r| r.contains(line_no));
+            let (num_style, text_style) = if highlighted {
+                (
+                    Style::default().fg(WARNING_COLOR).bg(HIGHLIGHT_BG).add_modifier(Modifier::BOLD),
+                    Style::default().fg(ACCENT_COLOR).bg(HIGHLIGHT_BG),
+                )
+            } else {
+                (Style::default().fg(BORDER_COLOR), Style::default().fg(FG_COLOR))
+            };
+            Line::from(vec![
+                Span::styled(format!(" {:>width$} ", line_no, width = gutter), num_style),
+                Span::styled(format!(" {}", text), text_style),
+            ])
+        })
+        .collect();
+
+    let title = match preview.highlight {
+        Some(r) => format!(" {} (L{}-L{}) ", preview.title, r.start, r.end),
+        None => format!(" {} ", preview.title),
+    };
+
+    let body = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(ACCENT_COLOR))
+            .style(Style::default().bg(BG_COLOR)),
+    );
+    f.render_widget(body, chunks[0]);
+
+    let help = Paragraph::new(Line::from(vec![
+        Span::styled("↑↓", Style::default().fg(ACCENT_COLOR).add_modifier(Modifier::BOLD)),
+        Span::styled(" Scroll", Style::default().fg(BORDER_COLOR)),
+        Span::styled("  │  ", Style::default().fg(BORDER_COLOR)),
+        Span::styled("PgUp/PgDn", Style::default().fg(ACCENT_COLOR).add_modifier(Modifier::BOLD)),
+        Span::styled(" Page", Style::default().fg(BORDER_COLOR)),
+        Span::styled("  │  ", Style::default().fg(BORDER_COLOR)),
+        Span::styled("←/Esc", Style::default().fg(ERROR_COLOR).add_modifier(Modifier::BOLD)),
+        Span::styled(" Back", Style::default().fg(BORDER_COLOR)),
+    ]))
+    .alignment(ratatui::layout::Alignment::Center)
+    .style(Style::default().bg(BG_COLOR));
+    f.render_widget(help, chunks[1]);
+}
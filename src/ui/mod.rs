@@ -11,9 +11,11 @@ use ratatui::{
 use std::io;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use crate::ui::components::preview::Preview;
 use crate::ui::components::toast::{Toast, ToastType};
 
-use crate::github::{GitHubClient, GitHubUrl, RepoItem};
+use crate::http;
+use crate::provider::{LineRange, Progress, Providers, RepoItem, RepoUrl};
 
 pub mod components;
 pub mod theme;
@@ -22,20 +24,28 @@ pub mod theme;
 pub enum AppMode {
     Input,
     Browse,
+    Preview,
 }
 
 pub struct AppState {
     pub mode: AppMode,
     pub url_input: String,
-    pub current_url: Option<GitHubUrl>,
+    pub current_url: Option<RepoUrl>,
     pub items: Vec<RepoItem>,
     pub cursor: usize,
     pub scroll_offset: usize,
     pub status_message: String,
     pub downloading: bool,
-    pub navigation_stack: Vec<(GitHubUrl, usize)>,
+    pub navigation_stack: Vec<(RepoUrl, usize)>,
     pub frame_count: u64,
     pub toast: Option<Toast>,
+    pub preview: Option<Preview>,
+    // Permalink range to highlight when this file is previewed
+    pub line_focus: Option<(String, LineRange)>,
+    // Where the current host's token came from; None browses anonymously
+    pub auth_source: Option<String>,
+    // GitHub may have left entries out of the current listing
+    pub partial: bool,
 }
 
 impl AppState {
@@ -52,6 +62,10 @@ impl AppState {
             navigation_stack: Vec::new(),
             frame_count: 0,
             toast: None,
+            preview: None,
+            auth_source: None,
+            partial: false,
+            line_focus: None,
         }
     }
 
@@ -109,6 +123,97 @@ impl AppState {
     pub fn get_selected_items(&self) -> Vec<RepoItem> {
         self.items.iter().filter(|i| i.selected).cloned().collect()
     }
+
+    // Show a freshly loaded listing, putting the cursor on `focus` and selecting it
+    pub fn open_listing(&mut self, items: Vec<RepoItem>, url: RepoUrl, focus: Option<&str>) {
+        self.items = items;
+        self.current_url = Some(url);
+        self.cursor = 0;
+        self.scroll_offset = 0;
+        if let Some(path) = focus
+            && let Some(idx) = self.items.iter().position(|i| i.path == path)
+        {
+            self.items[idx].selected = true;
+            self.cursor = idx;
+            self.adjust_scroll();
+        }
+        self.mode = AppMode::Browse;
+        self.status_message = String::new();
+    }
+}
+
+fn scroll_preview(s: &mut AppState, delta: isize) {
+    if let Some(preview) = s.preview.as_mut() {
+        preview.scroll_by(delta);
+    }
+}
+
+fn focus_path(url: &RepoUrl) -> Option<&str> {
+    url.is_file().then_some(url.path.as_str())
+}
+
+// Retry and rate-limit notices from the providers end up in the status line
+fn status_reporter(state: &Arc<Mutex<AppState>>) -> Progress {
+    let state = state.clone();
+    Arc::new(move |msg| {
+        let state = state.clone();
+        tokio::spawn(async move {
+            state.lock().await.status_message = msg;
+        });
+    })
+}
+
+// Resolve the ref, list the target folder and switch to browsing it
+async fn load_url(state: &Arc<Mutex<AppState>>, providers: &Providers, mut repo_url: RepoUrl) -> Result<()> {
+    let provider = providers.for_host(&repo_url.host);
+    provider.resolve_ref(&mut repo_url).await?;
+
+    let listing_url = repo_url.dir_url();
+    let listing = provider.list_dir(&listing_url).await?;
+    let mut items = listing.items;
+
+    state.lock().await.status_message = "Resolving LFS files...".to_string();
+    provider.resolve_lfs(&mut items, &repo_url).await;
+
+    let mut s = state.lock().await;
+    s.auth_source = provider.credential_source(&repo_url.host).map(|source| source.to_string());
+    s.open_listing(items, listing_url, focus_path(&repo_url));
+    s.partial = listing.partial;
+    s.line_focus = repo_url.lines.map(|range| (repo_url.path.clone(), range));
+    if listing.partial {
+        s.show_toast(format!("Partial view: {} did not list every entry", repo_url.host.name()), ToastType::Info);
+    } else {
+        s.show_toast("Repository Loaded!".to_string(), ToastType::Success);
+    }
+
+    let focused = s.line_focus.as_ref().and_then(|_| s.items.get(s.cursor).cloned());
+    drop(s);
+    // the listing loaded either way; a file that can't be shown is only worth a toast
+    if let Some(item) = focused
+        && let Err(e) = open_preview(state, providers, &item).await
+    {
+        state.lock().await.show_toast(format!("Preview Error: {}", e), ToastType::Error);
+    }
+    Ok(())
+}
+
+async fn open_preview(state: &Arc<Mutex<AppState>>, providers: &Providers, item: &RepoItem) -> Result<()> {
+    let download_url = item.download_url.as_ref().context("No download URL for file")?;
+    let host = match &state.lock().await.current_url {
+        Some(url) => url.host.clone(),
+        None => return Ok(()),
+    };
+    let content = providers.for_host(&host).raw_file(download_url).await?;
+    let content = String::from_utf8(content).context("File is not valid UTF-8 text")?;
+
+    let mut s = state.lock().await;
+    let highlight = match &s.line_focus {
+        Some((path, range)) if *path == item.path => Some(*range),
+        _ => None,
+    };
+    s.preview = Some(Preview::new(item.path.clone(), &content, highlight));
+    s.mode = AppMode::Preview;
+    Ok(())
 }
 
 pub async fn run_tui(initial_url: Option<String>) -> Result<()> {
@@ -125,45 +230,25 @@ pub async fn run_tui(initial_url: Option<String>) -> Result<()> {
     }
     let state = Arc::new(Mutex::new(state_init));
     
+    let providers = Arc::new(Providers::new()?);
+    providers.set_progress(status_reporter(&state));
+
     let result = if !state.lock().await.url_input.is_empty() {
-        let client = GitHubClient::new()?;
         let url_to_load = state.lock().await.url_input.clone();
         
-        match GitHubUrl::parse(&url_to_load) {
-            Ok(mut gh_url) => {
-                match client.fetch_contents(&gh_url.api_url()).await {
-                    Ok(mut items) => {
-                        client.resolve_lfs_files(&mut items, &gh_url.owner, &gh_url.repo).await;
-                        
-                        let mut s = state.lock().await;
-                        s.items = items;
-                        s.current_url = Some(gh_url);
-                        s.mode = AppMode::Browse;
-                        s.status_message = String::new();
-                        s.show_toast("Repository Loaded!".to_string(), ToastType::Success);
-                    }
-                    Err(_) => {
-                        if gh_url.branch == "main" {
-                            gh_url.branch = "master".to_string();
-                            if let Ok(mut items) = client.fetch_contents(&gh_url.api_url()).await {
-                                client.resolve_lfs_files(&mut items, &gh_url.owner, &gh_url.repo).await;
-                                
-                                let mut s = state.lock().await;
-                                s.items = items;
-                                s.current_url = Some(gh_url);
-                                s.mode = AppMode::Browse;
-                                s.status_message = String::new();
-                                s.show_toast("Repository Loaded!".to_string(), ToastType::Success);
-                            }
-                        }
-                    }
+        match RepoUrl::parse(&url_to_load) {
+            Ok(repo_url) => {
+                if let Err(e) = http::without_quota_wait(load_url(&state, &providers, repo_url)).await {
+                    state.lock().await.show_toast(format!("Error: {}", e), ToastType::Error);
                 }
             }
-            Err(_) => {}
+            Err(e) => {
+                state.lock().await.show_toast(format!("Invalid URL: {}", e), ToastType::Error);
+            }
         }
-        event_loop(&mut terminal, state).await
+        event_loop(&mut terminal, state, providers).await
     } else {
-        event_loop(&mut terminal, state).await
+        event_loop(&mut terminal, state, providers).await
     };
 
     disable_raw_mode()?;
@@ -176,8 +261,8 @@ pub async fn run_tui(initial_url: Option<String>) -> Result<()> {
 async fn event_loop(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     state: Arc<Mutex<AppState>>,
+    providers: Arc<Providers>,
 ) -> Result<()> {
-    let client = GitHubClient::new()?;
 
     loop {
         {
@@ -208,9 +293,20 @@ async fn event_loop(
                             scroll_offset: state_lock.scroll_offset,
                             status_msg: &state_lock.status_message,
                             is_downloading: state_lock.downloading,
+                            auth_source: state_lock.auth_source.as_deref(),
+                            rate_limit: state_lock
+                                .current_url
+                                .as_ref()
+                                .and_then(|url| providers.for_host(&url.host).rate_limit(&url.host)),
+                            partial: state_lock.partial,
                         };
                         components::browser::render(f, size, &browser_state);
                     }
+                    AppMode::Preview => {
+                        if let Some(ref preview) = state_lock.preview {
+                            components::preview::render(f, size, preview);
+                        }
+                    }
                 }
 
                 if let Some(ref toast) = state_lock.toast {
@@ -222,7 +318,9 @@ async fn event_loop(
         if event::poll(std::time::Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
                 if key.kind == event::KeyEventKind::Press {
-                    if handle_input(key, state.clone(), &client).await? {
+                    // the input task can't sleep through a quota and still take a quit key;
+                    // downloads run on their own task and may wait
+                    if http::without_quota_wait(handle_input(key, state.clone(), &providers)).await? {
                         break;
                     }
                 }
@@ -233,7 +331,7 @@ async fn event_loop(
     Ok(())
 }
 
-async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, client: &GitHubClient) -> Result<bool> {
+async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, providers: &Arc<Providers>) -> Result<bool> {
     let mut s = state.lock().await;
 
     if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
@@ -251,56 +349,11 @@ async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, client: &GitHu
                     s.status_message = "Fetching...".to_string();
                     drop(s);
                     
-                    match GitHubUrl::parse(&url) {
-                        Ok(mut gh_url) => {
-                            // Try fetching with the parsed branch
-                            match client.fetch_contents(&gh_url.api_url()).await {
-                                Ok(mut items) => {
-                                    // Resolve LFS files
-                                    {
-                                        let mut s = state.lock().await;
-                                        s.status_message = "Resolving LFS files...".to_string();
-                                    }
-                                    client.resolve_lfs_files(&mut items, &gh_url.owner, &gh_url.repo).await;
-                                    
-                                    let mut s = state.lock().await;
-                                    s.items = items;
-                                    s.current_url = Some(gh_url);
-                                    s.mode = AppMode::Browse;
-                                    s.status_message = String::new();
-                                    s.show_toast("Repository Loaded!".to_string(), ToastType::Success);
-                                }
-                                Err(e) => {
-                                    // If branch was defaulted to "main" and failed, try "master"
-                                    let err_str = format!("{}", e);
-                                    if gh_url.branch == "main" && err_str.contains("not found") {
-                                        gh_url.branch = "master".to_string();
-                                        match client.fetch_contents(&gh_url.api_url()).await {
-                                            Ok(mut items) => {
-                                                // Resolve LFS files
-                                                {
-                                                    let mut s = state.lock().await;
-                                                    s.status_message = "Resolving LFS files...".to_string();
-                                                }
-                                                client.resolve_lfs_files(&mut items, &gh_url.owner, &gh_url.repo).await;
-                                                
-                                                let mut s = state.lock().await;
-                                                s.items = items;
-                                                s.current_url = Some(gh_url);
-                                                s.mode = AppMode::Browse;
-                                                s.status_message = String::new();
-                                                s.show_toast("Repository Loaded!".to_string(), ToastType::Success);
-                                            }
-                                            Err(e2) => {
-                                                let mut s = state.lock().await;
-                                                s.show_toast(format!("Error: {}", e2), ToastType::Error);
-                                            }
-                                        }
-                                    } else {
-                                        let mut s = state.lock().await;
-                                        s.show_toast(format!("Error: {}", e), ToastType::Error);
-                                    }
-                                }
+                    match RepoUrl::parse(&url) {
+                        Ok(repo_url) => {
+                            if let Err(e) = load_url(&state, providers, repo_url).await {
+                                let mut s = state.lock().await;
+                                s.show_toast(format!("Error: {}", e), ToastType::Error);
                             }
                         }
                         Err(e) => {
@@ -333,10 +386,11 @@ async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, client: &GitHu
                         s.status_message = "Heading back...".to_string();
                         drop(s);
                         
-                        match client.fetch_contents(&prev_url.api_url()).await {
-                            Ok(items) => {
+                        match providers.for_host(&prev_url.host).list_dir(&prev_url).await {
+                            Ok(listing) => {
                                 let mut s = state.lock().await;
-                                s.items = items;
+                                s.items = listing.items;
+                                s.partial = listing.partial;
                                 s.current_url = Some(prev_url);
                                 s.cursor = prev_cursor;
                                 s.scroll_offset = 0; 
@@ -352,28 +406,21 @@ async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, client: &GitHu
                 },
                 KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
                     if let Some(item) = s.items.get(s.cursor).cloned() {
-                        if item.is_dir() {
-                            let cursor_pos = s.cursor;
-                            if let Some(current_url) = s.current_url.clone() {
-                                s.navigation_stack.push((current_url.clone(), cursor_pos));
-                                
-                                let new_path = if current_url.path.is_empty() {
-                                    item.name.clone()
-                                } else {
-                                    format!("{}/{}", current_url.path, item.name)
-                                };
-                                
-                                let new_url = GitHubUrl {
-                                    path: new_path,
-                                    ..current_url
-                                };
-                                
+                        if let Some(current_url) = s.current_url.clone() {
+                            if let Some(new_url) = current_url.child(&item) {
+                                let cursor_pos = s.cursor;
+                                s.navigation_stack.push((current_url, cursor_pos));
+                        
                                 drop(s);
-                                
-                                match client.fetch_contents(&new_url.api_url()).await {
-                                    Ok(items) => {
+                        
+                                match providers.for_host(&new_url.host).list_dir(&new_url).await {
+                                    Ok(listing) => {
                                         let mut s = state.lock().await;
-                                        s.items = items;
+                                        s.items = listing.items;
+                                        s.partial = listing.partial;
+                                        if listing.partial {
+                                            s.show_toast(format!("Partial view: {} did not list every entry", new_url.host.name()), ToastType::Info);
+                                        }
                                         s.current_url = Some(new_url);
                                         s.cursor = 0;
                                         s.scroll_offset = 0;
@@ -384,10 +431,21 @@ async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, client: &GitHu
                                         s.show_toast(format!("Nav Error: {}", e), ToastType::Error);
                                     }
                                 }
-                             }
+                            }
                         }
                     }
                 },
+                KeyCode::Char('p') => {
+                    if let Some(item) = s.items.get(s.cursor).cloned()
+                        && item.is_file()
+                    {
+                        drop(s);
+                        if let Err(e) = open_preview(&state, providers, &item).await {
+                            let mut s = state.lock().await;
+                            s.show_toast(format!("Preview Error: {}", e), ToastType::Error);
+                        }
+                    }
+                }
                 KeyCode::Char('d') | KeyCode::Char('D') => {
                     if s.get_selected_items().is_empty() {
                         s.show_toast("No items selected!".to_string(), ToastType::Info);
@@ -396,8 +454,9 @@ async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, client: &GitHu
                         drop(s);
                         
                         let s_clone = state.clone();
+                        let providers = providers.clone();
                         tokio::spawn(async move {
-                            if let Err(_e) = perform_download(s_clone).await {
+                            if let Err(_e) = perform_download(s_clone, providers).await {
                             }
                         });
                     }
@@ -405,17 +464,34 @@ async fn handle_input(key: KeyEvent, state: Arc<Mutex<AppState>>, client: &GitHu
                 _ => {}
             }
         }
+        AppMode::Preview => {
+            let page = 20;
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Char('Q') => return Ok(true),
+                KeyCode::Esc | KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
+                    s.preview = None;
+                    s.mode = AppMode::Browse;
+                }
+                KeyCode::Up | KeyCode::Char('k') => scroll_preview(&mut s, -1),
+                KeyCode::Down | KeyCode::Char('j') => scroll_preview(&mut s, 1),
+                KeyCode::PageUp => scroll_preview(&mut s, -page),
+                KeyCode::PageDown => scroll_preview(&mut s, page),
+                KeyCode::Home | KeyCode::Char('g') => scroll_preview(&mut s, isize::MIN),
+                KeyCode::End | KeyCode::Char('G') => scroll_preview(&mut s, isize::MAX),
+                _ => {}
+            }
+        }
     }
     
     Ok(false)
 }
 
-async fn perform_download(state: Arc<Mutex<AppState>>) -> Result<()> {
+async fn perform_download(state: Arc<Mutex<AppState>>, providers: Arc<Providers>) -> Result<()> {
     use crate::download::Downloader;
-    let (selected_items, _repo_path, repo_name) = {
+    let (selected_items, current_url) = {
         let s = state.lock().await;
         if let Some(url) = &s.current_url {
-            (s.get_selected_items(), format!("{}/{}", url.owner, url.repo), url.repo.clone())
+            (s.get_selected_items(), url.clone())
         } else {
             return Ok(());
         }
@@ -423,12 +499,12 @@ async fn perform_download(state: Arc<Mutex<AppState>>) -> Result<()> {
     
     let download_dir = dirs::download_dir()
         .context("Could not find User Downloads directory")?
-        .join(repo_name);
+        .join(&current_url.repo);
 
-    let downloader = Downloader::new(download_dir)?;
+    let downloader = Downloader::new(providers, download_dir)?;
     let state_c = state.clone();
     
-    let result = downloader.download_items(&selected_items, &_repo_path, move |msg| {
+    let result = downloader.download_items(&selected_items, &current_url, move |msg| {
         let s = state_c.clone();
         tokio::spawn(async move {
             let mut s = s.lock().await;